@@ -0,0 +1,49 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Error, Fields, Index, Member, Type};
+
+fn is_option(ty: &Type) -> bool {
+    matches!(ty, Type::Path(path) if path.qself.is_none() && path.path.segments.last().is_some_and(|segment| segment.ident == "Option"))
+}
+
+pub fn expand(mut input: DeriveInput) -> Result<TokenStream, Error> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new_spanned(&input.ident, "HLAPIArgs can only be derived for structs"));
+    };
+    let fields: Vec<(Member, &Type)> = match &data.fields {
+        Fields::Named(fields) => fields.named.iter().map(|field| (Member::Named(field.ident.clone().unwrap()), &field.ty)).collect(),
+        Fields::Unnamed(fields) => fields.unnamed.iter().enumerate().map(|(index, field)| (Member::Unnamed(Index::from(index)), &field.ty)).collect(),
+        Fields::Unit => Vec::new(),
+    };
+    let count = fields.len();
+
+    // Trailing `None`s are left out, from the last field backwards, as long as they are optional
+    let trims = fields.iter().enumerate().rev().take_while(|(_, (_, ty))| is_option(ty)).map(|(index, (member, _))| {
+        quote! { if length == #index + 1 && self.#member.is_none() { length = #index; } }
+    });
+    let elements = fields.iter().enumerate().map(|(index, (member, _))| {
+        quote! { if #index < length { ::oc2devices::__private::serde::ser::SerializeSeq::serialize_element(&mut seq, &self.#member)?; } }
+    });
+
+    let ident = input.ident.clone();
+    for parameter in input.generics.type_params_mut() {
+        parameter.bounds.push(parse_quote!(::oc2devices::__private::serde::Serialize));
+    }
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::oc2devices::__private::serde::Serialize for #ident #type_generics #where_clause {
+            fn serialize<S: ::oc2devices::__private::serde::Serializer>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error> {
+                #[allow(unused_mut)]
+                let mut length: usize = #count;
+                #(#trims)*
+                #[allow(unused_mut)]
+                let mut seq = serializer.serialize_seq(::core::option::Option::Some(length))?;
+                #(#elements)*
+                ::oc2devices::__private::serde::ser::SerializeSeq::end(seq)
+            }
+        }
+
+        impl #impl_generics ::oc2devices::args::HLAPIArgs for #ident #type_generics #where_clause { }
+    })
+}
@@ -0,0 +1,158 @@
+use proc_macro2::TokenStream;
+use quote::{quote, format_ident, ToTokens};
+use syn::{Error, FnArg, GenericArgument, ItemTrait, LitStr, Pat, PathArguments, ReturnType, TraitItem, Type};
+
+/// `set_redstone_output` to `setRedstoneOutput`, same as `oc2devices::device::camel_case`
+fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for character in name.trim_start_matches("r#").chars() {
+        match character {
+            '_' => upper = !camel.is_empty(),
+            _ if upper => { camel.extend(character.to_uppercase()); upper = false; }
+            _ => camel.push(character),
+        }
+    }
+    camel
+}
+
+/// `T` out of `HLAPIResult<T>`, if that's what `ty` is
+fn result_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else { return None };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "HLAPIResult" { return None; }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else { return None };
+    match arguments.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+fn is_unit(ty: &Type) -> bool { matches!(ty, Type::Tuple(tuple) if tuple.elems.is_empty()) }
+
+struct Method {
+    ident: syn::Ident,
+    java_name: String,
+    arguments: Vec<(syn::Ident, Type)>,
+    returns: Type, // `HLAPIResult` stripped
+}
+
+pub fn expand(component: LitStr, mut item: ItemTrait) -> Result<TokenStream, Error> {
+    let mut methods = Vec::new();
+
+    for trait_item in &mut item.items {
+        let TraitItem::Fn(function) = trait_item else {
+            return Err(Error::new_spanned(trait_item, "only methods are supported in a HLAPI component"));
+        };
+        if let Some(default) = &function.default { return Err(Error::new_spanned(default, "HLAPI component methods can't have a body")); }
+        if !function.sig.generics.params.is_empty() { return Err(Error::new_spanned(&function.sig.generics, "HLAPI component methods can't be generic")); }
+        match function.sig.receiver() {
+            Some(receiver) if receiver.reference.is_some() && receiver.mutability.is_some() => { }
+            _ => return Err(Error::new_spanned(&function.sig, "HLAPI component methods take `&mut self`")),
+        }
+
+        // `#[hlapi(name = "...")]` for names not following the camelCase convention
+        let mut java_name = None;
+        for attribute in function.attrs.iter().filter(|attribute| attribute.path().is_ident("hlapi")) {
+            attribute.parse_nested_meta(|meta| {
+                if !meta.path.is_ident("name") { return Err(meta.error("expected `name = \"...\"`")); }
+                java_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            })?;
+        }
+        function.attrs.retain(|attribute| !attribute.path().is_ident("hlapi"));
+
+        let mut arguments = Vec::new();
+        for input in function.sig.inputs.iter().skip(1) {
+            let FnArg::Typed(typed) = input else { unreachable!() };
+            let Pat::Ident(ident) = typed.pat.as_ref() else { return Err(Error::new_spanned(&typed.pat, "expected a plain argument name")); };
+            arguments.push((ident.ident.clone(), (*typed.ty).clone()));
+        }
+
+        let (returns, wrapped) = match &function.sig.output {
+            ReturnType::Default => (syn::parse_quote!(()), false),
+            ReturnType::Type(_, ty) => result_inner(ty).map_or((ty.as_ref().clone(), false), |inner| (inner.clone(), true)),
+        };
+        if !wrapped { function.sig.output = syn::parse_quote!(-> ::oc2devices::error::HLAPIResult<#returns>); }
+
+        methods.push(Method {
+            java_name: java_name.unwrap_or_else(|| camel_case(&function.sig.ident.to_string())),
+            ident: function.sig.ident.clone(),
+            arguments,
+            returns,
+        });
+    }
+
+    let (vis, name) = (&item.vis, &item.ident);
+    let client = format_ident!("{}Client", name);
+    let doc = format!("`{name}` over a device of the bus");
+
+    let declared = methods.iter().map(|method| {
+        let java_name = &method.java_name;
+        let parameters = method.arguments.iter().map(|(_, ty)| ty.to_token_stream().to_string());
+        let returns = method.returns.to_token_stream().to_string();
+        quote! { ::oc2devices::component::DeclaredMethod { name: #java_name, parameters: &[#(#parameters),*], returns: #returns } }
+    });
+
+    let implementations = methods.iter().map(|method| {
+        let (ident, java_name, returns) = (&method.ident, &method.java_name, &method.returns);
+        let names: Vec<_> = method.arguments.iter().map(|(name, _)| name).collect();
+        let types = method.arguments.iter().map(|(_, ty)| ty);
+        let parameters = match names.as_slice() {
+            [] => quote!(::oc2devices::types::EMPTY),
+            [single] => quote!((#single,)),
+            _ => quote!((#(#names),*)),
+        };
+        let call = if is_unit(returns) {
+            // Whatever the method returns is ignored, as `verify` allows
+            quote!(self.bus.raw_call::<_, _, ::core::option::Option<::oc2devices::__private::serde::de::IgnoredAny>>(self.device, #java_name, #parameters).map(drop))
+        } else {
+            quote!(self.bus.raw_call(self.device, #java_name, #parameters))
+        };
+        quote! {
+            fn #ident(&mut self, #(#names: #types),*) -> ::oc2devices::error::HLAPIResult<#returns> { #call }
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        #[doc = #doc]
+        #vis struct #client<'b, T: ::oc2devices::transport::Transport = ::oc2devices::transport::HvcTransport> {
+            bus: &'b mut ::oc2devices::HLAPIBus<T>,
+            device: ::oc2devices::types::HLAPIDeviceHandle,
+        }
+
+        impl<'b, T: ::oc2devices::transport::Transport> #client<'b, T> {
+            pub const COMPONENT: &'static str = #component;
+            pub const METHODS: &'static [::oc2devices::component::DeclaredMethod] = &[#(#declared),*];
+
+            pub fn new(bus: &'b mut ::oc2devices::HLAPIBus<T>, device: ::oc2devices::types::HLAPIDeviceHandle) -> Self { Self { bus, device } }
+
+            /// First device having the component
+            pub fn find(bus: &'b mut ::oc2devices::HLAPIBus<T>) -> ::oc2devices::error::HLAPIResult<Self> {
+                let device = bus.find(Self::COMPONENT)?;
+                Ok(Self::new(bus, device))
+            }
+
+            /// Same as `new`, after checking the declared methods against the ones of the device
+            pub fn verified(bus: &'b mut ::oc2devices::HLAPIBus<T>, device: ::oc2devices::types::HLAPIDeviceHandle) -> ::oc2devices::error::HLAPIResult<Self> {
+                let methods = bus.methods(device)?;
+                ::oc2devices::component::verify(&methods, Self::METHODS)?;
+                Ok(Self::new(bus, device))
+            }
+
+            pub fn find_verified(bus: &'b mut ::oc2devices::HLAPIBus<T>) -> ::oc2devices::error::HLAPIResult<Self> {
+                let device = bus.find(Self::COMPONENT)?;
+                Self::verified(bus, device)
+            }
+
+            pub fn device(&self) -> ::oc2devices::types::HLAPIDeviceHandle { self.device }
+            pub fn bus(&mut self) -> &mut ::oc2devices::HLAPIBus<T> { self.bus }
+        }
+
+        impl<T: ::oc2devices::transport::Transport> #name for #client<'_, T> {
+            #(#implementations)*
+        }
+    })
+}
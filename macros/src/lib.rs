@@ -0,0 +1,34 @@
+//! Procedural macros of `oc2devices`, re-exported from there
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemTrait, LitStr};
+
+mod component;
+mod args;
+
+/// Declares the methods of a component by hand, for devices without a dump
+/// ```ignore
+/// #[hlapi_component("redstone")]
+/// trait Redstone {
+///     fn get_redstone_input(&mut self, side: String) -> i32;
+///     #[hlapi(name = "setRedstoneOutput")]
+///     fn set_output(&mut self, side: String, value: i32);
+/// }
+/// ```
+/// Method names are mapped to camelCase, arguments are sent positionally and every method returns a `HLAPIResult`,
+/// a missing or unit return ignoring whatever the method returns. Also generates a `RedstoneClient` implementing the trait,
+/// whose `verified` constructors check the declared signatures against the live `methods()`
+#[proc_macro_attribute]
+pub fn hlapi_component(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let component = parse_macro_input!(attribute as LitStr);
+    let item = parse_macro_input!(item as ItemTrait);
+    component::expand(component, item).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+/// Sends the fields of a struct as positional parameters, in declaration order
+/// Trailing `Option` fields left to `None` are not sent at all, so that the Java side picks the shorter overload
+#[proc_macro_derive(HLAPIArgs)]
+pub fn derive_hlapi_args(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    args::expand(input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+use crate::types::Empty;
+
+/// Parameters sent as a JSON array, as the Java side expects them, required by every call
+/// Derive it on structs to send their fields in declaration order, trailing `None` fields being left out:
+/// ```ignore
+/// #[derive(HLAPIArgs)]
+/// struct SetOutput { side: String, value: i32, pulse: Option<u32> }
+/// bus.raw_call(device, "setRedstoneOutput", SetOutput { side: "up".into(), value: 15, pulse: None })?; // ["up", 15]
+/// ```
+pub trait HLAPIArgs: Serialize { }
+
+impl HLAPIArgs for Empty { }
+impl<T: Serialize> HLAPIArgs for Vec<T> { }
+impl<T: Serialize> HLAPIArgs for [T] { }
+impl<T: HLAPIArgs + ?Sized> HLAPIArgs for &T { }
+
+macro_rules! tuple_args {
+    ($($name:ident),+) => { impl<$($name: Serialize),+> HLAPIArgs for ($($name,)+) { } };
+}
+
+tuple_args!(A);
+tuple_args!(A, B);
+tuple_args!(A, B, C);
+tuple_args!(A, B, C, D);
+tuple_args!(A, B, C, D, E);
+tuple_args!(A, B, C, D, E, F);
+tuple_args!(A, B, C, D, E, F, G);
+tuple_args!(A, B, C, D, E, F, G, H);
+tuple_args!(A, B, C, D, E, F, G, H, I);
+tuple_args!(A, B, C, D, E, F, G, H, I, J);
+tuple_args!(A, B, C, D, E, F, G, H, I, J, K);
+tuple_args!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+/// Positional parameters out of a list of values, `args!()` being no parameters at all
+/// e.g. `bus.raw_call(device, "setRedstoneOutput", args!("up", 15))`
+#[macro_export]
+macro_rules! args {
+    () => { $crate::types::EMPTY };
+    ($($arg:expr),+ $(,)?) => { ($($arg,)+) };
+}
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{Result as IOResult, ErrorKind as IOErrorKind, IsTerminal, Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures_core::Stream;
+use serde::{ser::Serialize, de::DeserializeOwned};
+use serde_json::value::RawValue;
+use tokio::io::unix::AsyncFd;
+use crate::types::*;
+use crate::error::*;
+use crate::args::HLAPIArgs;
+use crate::buffer::ReadBuffer;
+use crate::transport::MAIN_BUS;
+use crate::terminal::{TermiosGuard, TtyConfig};
+use crate::{framer, encode, decode, DELIM, READ_BUF, DRAIN_GRACE};
+
+/// Async counterpart of `HLAPIBus`, the descriptor being registered with the tokio reactor instead of a private epoll
+/// Must be created from within a tokio runtime
+pub struct AsyncHLAPIBus {
+    terminal: Option<TermiosGuard>, // dropped first, while the descriptor is still open
+    handle: AsyncFd<File>,
+    buffer: ReadBuffer,
+    timeout: Option<Duration>, // None waits forever
+    stale: bool, // a late answer from a timed out or dropped call may still come
+    skipped: usize,
+}
+
+impl AsyncHLAPIBus {
+    pub fn main_bus() -> IOResult<Self> { Self::open(MAIN_BUS) }
+
+    /// Opens a tty and puts it in raw mode until dropped, anything else (pipes, sockets...) is used as is
+    pub fn open(path: impl AsRef<std::path::Path>) -> IOResult<Self> {
+        Self::open_with(path, &TtyConfig::default())
+    }
+
+    pub fn open_with(path: impl AsRef<std::path::Path>, config: &TtyConfig) -> IOResult<Self> {
+        let file = File::options().read(true).write(true).open(path)?;
+        let terminal = if file.is_terminal() { Some(TermiosGuard::apply(file.as_raw_fd(), config)?) } else { None };
+        Ok(Self { terminal, ..Self::from_file(file)? })
+    }
+
+    /// Takes over an already configured descriptor, switching it to non-blocking mode
+    pub fn from_file(file: File) -> IOResult<Self> {
+        set_nonblocking(file.as_raw_fd())?;
+        Ok(Self { terminal: None, handle: AsyncFd::new(file)?, buffer: ReadBuffer::new(READ_BUF), timeout: None, stale: false, skipped: 0 })
+    }
+
+    /// # Safety
+    /// `descriptor` must be an open file descriptor owned by nobody else
+    pub unsafe fn from_raw_fd(descriptor: RawFd) -> IOResult<Self> { Self::from_file(File::from_raw_fd(descriptor)) }
+
+    pub fn into_raw_fd(mut self) -> RawFd {
+        self.terminal = None; // restored while still open
+        self.handle.into_inner().into_raw_fd()
+    }
+
+    /// Default timeout for every call, a timed out call returns `HLAPIError::Timeout` and resets the bus
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) { self.timeout = timeout; }
+    pub fn timeout(&self) -> Option<Duration> { self.timeout }
+
+    pub fn skipped_bytes(&self) -> usize { self.skipped }
+
+    pub async fn list(&mut self) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        match self.exchange::<&'static str, Empty, Void>(&HLAPISend::List, "device list").await? {
+            HLAPIReceive::List(devices) => Ok(devices),
+            other => Err(HLAPIError::unexpected("list", other)),
+        }
+    }
+
+    pub async fn methods(&mut self, device: HLAPIDeviceHandle) -> HLAPIResult<Vec<HLAPIMethod>> {
+        match self.exchange::<&'static str, Empty, Void>(&HLAPISend::Methods(device), "method list").await? {
+            HLAPIReceive::Methods(methods) => Ok(methods),
+            other => Err(HLAPIError::unexpected("methods", other)),
+        }
+    }
+
+    pub async fn find(&mut self, name: &str) -> HLAPIResult<HLAPIDeviceHandle> {
+        for HLAPIDeviceDescriptor { device_id, components } in self.list().await? {
+            if components.into_iter().any(|dev| name == dev) { return Ok(device_id); }
+        }
+        Err(HLAPIError::DeviceNotFound(format!("component {name}")))
+    }
+
+    pub async fn raw_call<InTuple: HLAPIArgs, OutTuple: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: &str, args: InTuple) -> HLAPIResult<OutTuple> {
+        let request = HLAPISend::Invoke { device_id: device, method_name: method, parameters: args };
+        match self.exchange(&request, &format!("result of {method}")).await? {
+            HLAPIReceive::Result(tuple) => Ok(tuple),
+            other => Err(HLAPIError::unexpected("result", other)),
+        }
+    }
+
+    /// Items of an array result, decoded one by one as the stream gets polled
+    /// The packet is received whole before the stream is returned, the bus being free again right away
+    pub async fn raw_call_streamed<InTuple: HLAPIArgs, OutItem: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: &str, args: InTuple) -> HLAPIResult<ResultStream<OutItem>> {
+        let request = HLAPISend::Invoke { device_id: device, method_name: method, parameters: args };
+        match self.exchange::<_, _, Option<Vec<Box<RawValue>>>>(&request, &format!("result of {method}")).await? {
+            HLAPIReceive::Result(items) => Ok(ResultStream {
+                items: items.unwrap_or_default().into_iter(),
+                context: format!("item of the result of {method}"),
+                marker: std::marker::PhantomData,
+            }),
+            other => Err(HLAPIError::unexpected("result", other)),
+        }
+    }
+
+    /// Sends DELIM back into the socket, it makes so on the Java side, it clears the buffer, effectively resetting the state
+    pub async fn reset(&mut self) -> IOResult<()> { self.write_all(DELIM).await }
+
+    /// Discards everything received until the bus stays quiet for `grace`, returns the amount of bytes dropped
+    pub async fn drain(&mut self, grace: Duration) -> IOResult<usize> {
+        let mut discarded = self.buffer.clear();
+        while let Ok(result) = tokio::time::timeout(grace, self.fill()).await {
+            result?;
+            discarded += self.buffer.clear();
+        }
+        self.stale = false;
+        Ok(discarded)
+    }
+
+    async fn exchange<Name: AsRef<str> + Serialize, Tuple: Serialize, OutTuple: DeserializeOwned>
+    (&mut self, request: &HLAPISend<Name, Tuple>, context: &str) -> HLAPIResult<HLAPIReceive<OutTuple>> {
+        if self.stale { self.drain(DRAIN_GRACE).await?; }
+        let packet = encode(request)?;
+
+        // Until a frame gets decoded, in case the future is dropped while waiting
+        self.stale = true;
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.send_receive(&packet, context)).await.unwrap_or(Err(HLAPIError::Timeout)),
+            None => self.send_receive(&packet, context).await,
+        };
+        if let Err(HLAPIError::Timeout) = result { self.reset().await?; }
+        result
+    }
+
+    async fn send_receive<OutTuple: DeserializeOwned>(&mut self, packet: &[u8], context: &str) -> HLAPIResult<HLAPIReceive<OutTuple>> {
+        self.write_all(packet).await?;
+        loop {
+            if let Some(frame) = framer::find_frame(self.buffer.buffered()) {
+                let data = decode(&self.buffer.buffered()[frame.payload], context);
+                self.buffer.consume(frame.end);
+                self.skipped += frame.skipped;
+                self.stale = false;
+                return data;
+            }
+            self.fill().await?;
+        }
+    }
+
+    /// Reads whatever is available into the buffer
+    async fn fill(&mut self) -> IOResult<()> {
+        loop {
+            let mut guard = self.handle.readable().await?;
+            let buffer = &mut self.buffer;
+            match guard.try_io(|handle| (&mut handle.get_ref()).read(buffer.spare_mut())) {
+                Ok(Ok(0)) => Err(IOErrorKind::UnexpectedEof)?,
+                Ok(Ok(count)) => { buffer.commit(count); return Ok(()); }
+                Ok(Err(error)) => Err(error)?,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    async fn write_all(&mut self, mut bytes: &[u8]) -> IOResult<()> {
+        while !bytes.is_empty() {
+            let mut guard = self.handle.writable().await?;
+            match guard.try_io(|handle| (&mut handle.get_ref()).write(bytes)) {
+                Ok(Ok(0)) => Err(IOErrorKind::WriteZero)?,
+                Ok(Ok(count)) => bytes = &bytes[count..],
+                Ok(Err(error)) => Err(error)?,
+                Err(_would_block) => continue,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set_nonblocking(descriptor: RawFd) -> IOResult<()> {
+    // SAFETY: only flags of a descriptor we own are changed
+    unsafe {
+        let flags = libc::fcntl(descriptor, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(descriptor, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            Err(std::io::Error::last_os_error())?
+        }
+    }
+    Ok(())
+}
+
+/// Stream of the items of an array result, see `AsyncHLAPIBus::raw_call_streamed`
+pub struct ResultStream<OutItem> {
+    items: std::vec::IntoIter<Box<RawValue>>,
+    context: String,
+    marker: std::marker::PhantomData<fn() -> OutItem>,
+}
+
+impl<OutItem: DeserializeOwned> Iterator for ResultStream<OutItem> {
+    type Item = HLAPIResult<OutItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.items.next()?;
+        Some(serde_json::from_str(item.get()).map_err(|error| HLAPIError::deserialize(&self.context, error)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.items.size_hint() }
+}
+
+impl<OutItem: DeserializeOwned> Stream for ResultStream<OutItem> {
+    type Item = HLAPIResult<OutItem>;
+
+    fn poll_next(self: Pin<&mut Self>, _context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.get_mut().next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.items.size_hint() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::thread::{self, JoinHandle};
+
+    const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    fn bus() -> (AsyncHLAPIBus, UnixStream) {
+        let (client, server) = UnixStream::pair().unwrap();
+        (AsyncHLAPIBus::from_file(File::from(std::os::fd::OwnedFd::from(client))).unwrap(), server)
+    }
+
+    /// Answers the requests with `replies` in order, `None` leaving one unanswered
+    fn serve(mut server: UnixStream, replies: &'static [Option<&'static str>]) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let (mut replies, mut pending, mut bytes) = (replies.iter(), 0, [0; 256]);
+            while let Ok(count @ 1..) = server.read(&mut bytes) {
+                for &byte in &bytes[..count] {
+                    if byte != DELIM[0] { pending += 1; continue; }
+                    if std::mem::take(&mut pending) == 0 { continue; } // reset
+                    if let Some(Some(reply)) = replies.next() { write!(server, "\0{reply}\0").unwrap(); }
+                }
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn calls_and_streams() {
+        let (mut bus, server) = bus();
+        let _server = serve(server, &[Some(r#"{"type":"result","data":5}"#), Some(r#"{"type":"result","data":[1,2]}"#)]);
+        assert_eq!(bus.raw_call::<_, i32>(DEVICE, "get", EMPTY).await.unwrap(), 5);
+        let items: Vec<i32> = bus.raw_call_streamed(DEVICE, "list", EMPTY).await.unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(items, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn timeouts_reset_the_bus() {
+        let (mut bus, server) = bus();
+        let mut late = server.try_clone().unwrap();
+        let _server = serve(server, &[None, Some(r#"{"type":"result","data":2}"#)]);
+        bus.set_timeout(Some(Duration::from_millis(50)));
+        assert!(matches!(bus.raw_call::<_, i32>(DEVICE, "get", EMPTY).await, Err(HLAPIError::Timeout)));
+        write!(late, "\0{{\"type\":\"result\",\"data\":1}}\0").unwrap();
+        assert_eq!(bus.raw_call::<_, i32>(DEVICE, "get", EMPTY).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn dropped_calls_leave_the_bus_stale() {
+        let (mut bus, server) = bus();
+        let mut late = server.try_clone().unwrap();
+        let _server = serve(server, &[None, Some(r#"{"type":"result","data":2}"#)]);
+        assert!(tokio::time::timeout(Duration::from_millis(50), bus.raw_call::<_, i32>(DEVICE, "get", EMPTY)).await.is_err());
+        write!(late, "\0{{\"type\":\"result\",\"data\":1}}\0").unwrap();
+        assert_eq!(bus.raw_call::<_, i32>(DEVICE, "get", EMPTY).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn opens_non_terminals_as_is() {
+        let path = std::env::temp_dir().join(format!("oc2devices-fifo-{}", std::process::id()));
+        let name = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).unwrap();
+        // SAFETY: `name` is a valid C string
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+        let bus = AsyncHLAPIBus::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(bus.unwrap().terminal.is_none());
+    }
+}
@@ -0,0 +1,39 @@
+//! Compares two schema files from `hlapi-schema`
+//! `hlapi-schema-diff [--json] OLD NEW`, exits with 1 on breaking changes and 2 on errors
+
+use std::process::ExitCode;
+use oc2devices::schema::Schema;
+
+const USAGE: &str = "usage: hlapi-schema-diff [--json] OLD NEW";
+
+fn run() -> Result<bool, String> {
+    let (mut json, mut paths) = (false, Vec::new());
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => { println!("{USAGE}"); return Ok(false); }
+            _ if !arg.starts_with("--") => paths.push(arg),
+            _ => Err(USAGE)?,
+        }
+    }
+    let [old, new] = paths.as_slice() else { return Err(USAGE.to_owned()) };
+
+    let read = |path: &String| Schema::read(path).map_err(|error| format!("could not read {path}: {error}"));
+    let diff = read(old)?.diff(&read(new)?);
+
+    if json { println!("{}", serde_json::to_string_pretty(&diff).map_err(|error| error.to_string())?); }
+    else {
+        for change in &diff.changes { println!("{change}"); }
+        if diff.is_empty() { eprintln!("no changes"); }
+        else { eprintln!("{} changes, {} breaking", diff.changes.len(), diff.breaking_changes().count()); }
+    }
+    Ok(diff.breaking)
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(false) => ExitCode::SUCCESS,
+        Ok(true) => ExitCode::from(1),
+        Err(error) => { eprintln!("{error}"); ExitCode::from(2) }
+    }
+}
@@ -0,0 +1,47 @@
+//! Snapshots the methods of every device on the bus to a schema file
+//! `hlapi-schema [--bus PATH] [--timeout SECONDS] [OUTPUT]`, printed on stdout without `OUTPUT`
+
+use std::process::ExitCode;
+use std::time::Duration;
+use oc2devices::HLAPIBus;
+use oc2devices::schema::Schema;
+
+const USAGE: &str = "usage: hlapi-schema [--bus PATH] [--timeout SECONDS] [OUTPUT]";
+
+fn run() -> Result<(), String> {
+    let (mut bus_path, mut timeout, mut output) = (None, Some(Duration::from_secs(5)), None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bus" => bus_path = Some(args.next().ok_or(USAGE)?),
+            "--timeout" => {
+                let seconds: f64 = args.next().ok_or(USAGE)?.parse().map_err(|_| USAGE)?;
+                timeout = (seconds > 0.0).then(|| Duration::from_secs_f64(seconds)); // 0 waits forever
+            }
+            "-h" | "--help" => { println!("{USAGE}"); return Ok(()); }
+            _ if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+            _ => Err(USAGE)?,
+        }
+    }
+
+    let mut bus = match &bus_path {
+        Some(path) => HLAPIBus::open(path),
+        None => HLAPIBus::main_bus(),
+    }.map_err(|error| format!("could not open the bus: {error}"))?;
+    bus.set_timeout(timeout);
+
+    let schema = Schema::capture(&mut bus).map_err(|error| error.to_string())?;
+    match output {
+        Some(path) => schema.write(&path).map_err(|error| format!("could not write {path}: {error}"))?,
+        None => println!("{}", serde_json::to_string_pretty(&schema).map_err(|error| error.to_string())?),
+    }
+    eprintln!("{} component types", schema.components.len());
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => { eprintln!("{error}"); ExitCode::FAILURE }
+    }
+}
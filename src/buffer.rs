@@ -0,0 +1,87 @@
+use std::io::{Result as IOResult, BufRead, Read};
+
+/// Read buffer owned by the bus and kept across calls, so that bytes read past the end of a packet are never lost
+pub struct ReadBuffer {
+    data: Vec<u8>, // always fully initialized, its length is the capacity
+    start: usize, // first unread byte
+    end: usize, // last buffered byte + 1
+}
+
+impl ReadBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { data: vec![0; capacity], start: 0, end: 0 }
+    }
+
+    pub fn capacity(&self) -> usize { self.data.len() }
+
+    /// Received but not yet processed bytes
+    pub fn buffered(&self) -> &[u8] { &self.data[self.start..self.end] }
+
+    pub fn is_empty(&self) -> bool { self.start == self.end }
+
+    pub fn consume(&mut self, amount: usize) {
+        self.start = (self.start + amount).min(self.end);
+    }
+
+    /// Drops everything buffered, returns the amount of bytes dropped
+    pub fn clear(&mut self) -> usize {
+        let dropped = self.end - self.start;
+        (self.start, self.end) = (0, 0);
+        dropped
+    }
+
+    /// Free room after the buffered bytes, moving them to the front first, to be filled then `commit`ed
+    /// Doubles the capacity when full, so that a whole packet can be buffered
+    pub fn spare_mut(&mut self) -> &mut [u8] {
+        if self.start > 0 {
+            self.data.copy_within(self.start..self.end, 0);
+            (self.start, self.end) = (0, self.end - self.start);
+        }
+        if self.end == self.data.len() {
+            self.data.resize(2 * self.data.len().max(1), 0);
+        }
+        &mut self.data[self.end..]
+    }
+
+    /// Marks `amount` bytes of `spare_mut` as filled
+    pub fn commit(&mut self, amount: usize) {
+        self.end = (self.end + amount).min(self.data.len());
+    }
+
+    /// Buffered view over `inner`, which is only read from once everything buffered got consumed
+    pub fn reader<'a, R: Read + ?Sized>(&'a mut self, inner: &'a mut R) -> BufferedReader<'a, R> {
+        BufferedReader { buffer: self, inner }
+    }
+}
+
+pub struct BufferedReader<'a, R: Read + ?Sized> {
+    buffer: &'a mut ReadBuffer,
+    inner: &'a mut R,
+}
+
+impl<R: Read + ?Sized> BufRead for BufferedReader<'_, R> {
+    fn fill_buf(&mut self) -> IOResult<&[u8]> {
+        if self.buffer.is_empty() {
+            self.buffer.clear();
+            let count = self.inner.read(self.buffer.spare_mut())?;
+            self.buffer.commit(count);
+        }
+        Ok(self.buffer.buffered())
+    }
+
+    fn consume(&mut self, amount: usize) { self.buffer.consume(amount); }
+}
+
+impl<R: Read + ?Sized> Read for BufferedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        // Big reads skip the buffer entirely, as BufReader does
+        if self.buffer.is_empty() && buf.len() >= self.buffer.capacity() {
+            return self.inner.read(buf);
+        }
+        let available = self.fill_buf()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.consume(count);
+        Ok(count)
+    }
+}
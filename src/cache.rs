@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use crate::types::*;
+
+/// `list` and `methods` results kept by the bus, see `HLAPIBus::enable_cache`
+#[derive(Clone, Debug, Default)]
+pub struct DeviceCache {
+    ttl: Option<Duration>, // None never expires
+    list: Option<(Instant, Vec<HLAPIDeviceDescriptor>)>,
+    methods: HashMap<HLAPIDeviceHandle, (Instant, Vec<HLAPIMethod>)>,
+}
+
+impl DeviceCache {
+    pub fn new(ttl: Option<Duration>) -> Self { Self { ttl, ..Self::default() } }
+
+    pub fn ttl(&self) -> Option<Duration> { self.ttl }
+
+    fn fresh(&self, stored: Instant) -> bool {
+        self.ttl.is_none_or(|ttl| stored.elapsed() < ttl)
+    }
+
+    pub fn list(&self) -> Option<&[HLAPIDeviceDescriptor]> {
+        self.list.as_ref().filter(|(stored, _)| self.fresh(*stored)).map(|(_, devices)| devices.as_slice())
+    }
+
+    pub fn methods(&self, device: HLAPIDeviceHandle) -> Option<&[HLAPIMethod]> {
+        self.methods.get(&device).filter(|(stored, _)| self.fresh(*stored)).map(|(_, methods)| methods.as_slice())
+    }
+
+    pub fn store_list(&mut self, devices: Vec<HLAPIDeviceDescriptor>) {
+        self.list = Some((Instant::now(), devices));
+    }
+
+    pub fn store_methods(&mut self, device: HLAPIDeviceHandle, methods: Vec<HLAPIMethod>) {
+        self.methods.insert(device, (Instant::now(), methods));
+    }
+
+    pub fn invalidate(&mut self) {
+        self.list = None;
+        self.methods.clear();
+    }
+
+    /// Forgets the device methods, and the device list it may not be part of anymore
+    pub fn invalidate_device(&mut self, device: HLAPIDeviceHandle) {
+        self.list = None;
+        self.methods.remove(&device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use serde_json::json;
+    use crate::mock::{MockBus, MockDevice, MockTransport};
+    use crate::error::RemoteErrorKind;
+    use super::*;
+
+    const FIRST: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+    const SECOND: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(2);
+
+    fn bus() -> MockBus {
+        let device = |id| MockDevice::new(id, ["redstone"]).method("getRedstoneInput", &["java.lang.String"], "int", |_| Ok(json!(0)));
+        let mut bus = MockBus::new(MockTransport::new().with_device(device(FIRST)).with_device(device(SECOND)));
+        bus.enable_cache(None);
+        bus
+    }
+
+    #[test]
+    fn expires_after_the_ttl() {
+        let mut cache = DeviceCache::new(Some(Duration::from_millis(20)));
+        cache.store_list(Vec::new());
+        cache.store_methods(FIRST, Vec::new());
+        assert!(cache.list().is_some() && cache.methods(FIRST).is_some() && cache.methods(SECOND).is_none());
+        thread::sleep(Duration::from_millis(30));
+        assert!(cache.list().is_none() && cache.methods(FIRST).is_none());
+    }
+
+    #[test]
+    fn invalidates_a_single_device() {
+        let mut cache = DeviceCache::new(None);
+        cache.store_list(Vec::new());
+        cache.store_methods(FIRST, Vec::new());
+        cache.store_methods(SECOND, Vec::new());
+        cache.invalidate_device(FIRST);
+        assert!(cache.list().is_none() && cache.methods(FIRST).is_none() && cache.methods(SECOND).is_some());
+        cache.invalidate();
+        assert!(cache.methods(SECOND).is_none());
+    }
+
+    #[test]
+    fn bus_answers_from_the_cache() {
+        let mut bus = bus();
+        for _ in 0..3 {
+            assert_eq!(bus.list().unwrap().len(), 2);
+            assert_eq!(bus.methods(FIRST).unwrap().len(), 1);
+        }
+        assert_eq!(bus.transport().received().len(), 2);
+
+        bus.refresh_list().unwrap();
+        bus.invalidate_cache();
+        bus.methods(FIRST).unwrap();
+        assert_eq!(bus.transport().received().len(), 4);
+    }
+
+    #[test]
+    fn bus_forgets_unknown_devices() {
+        let mut bus = bus();
+        bus.list().unwrap();
+        bus.methods(FIRST).unwrap();
+        bus.methods(SECOND).unwrap();
+        bus.transport_mut().remove_device(FIRST);
+
+        let error = bus.raw_call::<_, _, i32>(FIRST, "getRedstoneInput", ("up",)).unwrap_err();
+        assert_eq!(error.remote_kind(), Some(RemoteErrorKind::UnknownDevice));
+        let cache = bus.cache().unwrap();
+        assert!(cache.list().is_none() && cache.methods(FIRST).is_none() && cache.methods(SECOND).is_some());
+        assert_eq!(bus.list().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::Result as IOResult;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::types::*;
+use crate::error::*;
+use crate::signature::{self, JavaType, Primitive};
+use crate::transport::Transport;
+use crate::device::snake_case;
+use crate::schema::Schema;
+use crate::HLAPIBus;
+
+/// A device and its methods, as found in the JSON dumps `Generator` reads
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+pub struct DeviceDump {
+    #[serde(flatten)]
+    pub descriptor: HLAPIDeviceDescriptor,
+    pub methods: Vec<HLAPIMethod>,
+}
+
+impl DeviceDump {
+    /// Every device on the bus
+    pub fn capture<T: Transport>(bus: &mut HLAPIBus<T>) -> HLAPIResult<Vec<Self>> {
+        bus.list()?.into_iter()
+            .map(|descriptor| Ok(Self { methods: bus.methods(descriptor.device_id)?, descriptor }))
+            .collect()
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> IOResult<Vec<Self>> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "final", "fn", "for",
+    "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Valid snake_case identifier, `r#` escaped if it's a keyword
+fn identifier(name: &str) -> String {
+    let mut identifier: String = snake_case(name).chars().map(|character| if character.is_ascii_alphanumeric() { character } else { '_' }).collect();
+    if identifier.is_empty() || identifier.starts_with(|character: char| character.is_ascii_digit()) { identifier.insert(0, '_'); }
+    match identifier.as_str() {
+        "self" | "super" | "crate" | "Self" => identifier + "_",
+        keyword if KEYWORDS.contains(&keyword) => format!("r#{identifier}"),
+        _ => identifier,
+    }
+}
+
+/// `item_handler` to `ItemHandler`
+fn pascal_case(name: &str) -> String {
+    let mut pascal: String = name.split(|character: char| !character.is_ascii_alphanumeric())
+        .flat_map(|word| {
+            let mut characters = word.chars();
+            characters.next().map(|first| first.to_ascii_uppercase()).into_iter().chain(characters)
+        })
+        .collect();
+    if pascal.is_empty() || pascal.starts_with(|character: char| character.is_ascii_digit()) { pascal.insert(0, 'C'); }
+    pascal
+}
+
+fn doc(out: &mut String, indent: &str, text: &str) {
+    for line in text.split('\n') {
+        let _ = writeln!(out, "{indent}///{}{}", if line.is_empty() { "" } else { " " }, line.trim_end());
+    }
+}
+
+/// Generates one trait and one client struct per component, out of `DeviceDump`s or a `Schema`
+/// e.g. from a `build.rs`, `Generator::new().generate_file("devices.json", out_dir.join("components.rs"))`,
+/// then `include!(concat!(env!("OUT_DIR"), "/components.rs"));`
+pub struct Generator {
+    crate_path: String,
+}
+
+impl Generator {
+    pub fn new() -> Self { Self { crate_path: "::oc2devices".to_owned() } }
+
+    /// How the generated code refers to this crate, `crate` when generating from within it
+    pub fn crate_path(mut self, path: impl Into<String>) -> Self {
+        self.crate_path = path.into();
+        self
+    }
+
+    /// Parameters being given by value, strings and arrays by reference
+    pub fn rust_type(&self, java: &JavaType, parameter: bool) -> String {
+        let krate = &self.crate_path;
+        match java {
+            JavaType::Void => "()".to_owned(),
+            JavaType::Primitive(primitive) => Self::primitive(*primitive).to_owned(),
+            JavaType::Boxed(primitive) => format!("Option<{}>", Self::primitive(*primitive)),
+            JavaType::String if parameter => "&str".to_owned(),
+            JavaType::String => "String".to_owned(),
+            JavaType::Array(element) if parameter => format!("&[{}]", self.rust_type(element, false)),
+            JavaType::Array(element) => format!("Vec<{}>", self.rust_type(element, false)),
+            JavaType::List => format!("Vec<{krate}::value::HLAPIValue>"),
+            JavaType::Map => format!("::std::collections::BTreeMap<String, {krate}::value::HLAPIValue>"),
+            JavaType::Object(_) => format!("{krate}::value::HLAPIValue"), // enums are strings, anything else is unknown
+        }
+    }
+
+    fn primitive(primitive: Primitive) -> &'static str {
+        match primitive {
+            Primitive::Boolean => "bool", Primitive::Byte => "i8", Primitive::Short => "i16", Primitive::Int => "i32",
+            Primitive::Long => "i64", Primitive::Float => "f32", Primitive::Double => "f64", Primitive::Char => "char",
+        }
+    }
+
+    pub fn generate(&self, dump: &[DeviceDump]) -> String {
+        self.generate_schema(&Schema::from_devices(dump))
+    }
+
+    pub fn generate_schema(&self, schema: &Schema) -> String {
+        let mut out = String::from("// Generated from a HLAPI methods dump, do not edit\n");
+        for (component, methods) in &schema.components {
+            out.push('\n');
+            self.component(&mut out, component, &methods.methods);
+        }
+        out
+    }
+
+    pub fn generate_file(&self, dump: impl AsRef<Path>, output: impl AsRef<Path>) -> IOResult<()> {
+        std::fs::write(output, self.generate(&DeviceDump::read(dump)?))
+    }
+
+    /// Same as `generate_file`, out of a `Schema` file
+    pub fn generate_schema_file(&self, schema: impl AsRef<Path>, output: impl AsRef<Path>) -> IOResult<()> {
+        std::fs::write(output, self.generate_schema(&Schema::read(schema)?))
+    }
+
+    fn component(&self, out: &mut String, component: &str, methods: &[HLAPIMethod]) {
+        let krate = &self.crate_path;
+        let (name, client) = (pascal_case(component), format!("{}Client", pascal_case(component)));
+
+        // Overloads get numbered, in the order the Java side lists them
+        let mut seen = BTreeMap::<String, usize>::new();
+        let methods: Vec<(String, &HLAPIMethod)> = methods.iter().map(|method| {
+            let count = seen.entry(identifier(&method.name)).or_default();
+            *count += 1;
+            let rust = if *count == 1 { identifier(&method.name) } else { format!("{}_{count}", identifier(&method.name).trim_start_matches("r#")) };
+            (rust, method)
+        }).collect();
+
+        let _ = writeln!(out, "/// `{component}` component");
+        let _ = writeln!(out, "pub trait {name} {{");
+        for (rust, method) in &methods {
+            self.method_doc(out, method);
+            let _ = writeln!(out, "    fn {};", self.method_head(rust, method));
+        }
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "/// `{name}` over a device of the bus");
+        let _ = writeln!(out, "pub struct {client}<'b, T: {krate}::transport::Transport = {krate}::transport::HvcTransport> {{");
+        let _ = writeln!(out, "    bus: &'b mut {krate}::HLAPIBus<T>,");
+        let _ = writeln!(out, "    device: {krate}::types::HLAPIDeviceHandle,");
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "impl<'b, T: {krate}::transport::Transport> {client}<'b, T> {{");
+        let _ = writeln!(out, "    pub const COMPONENT: &'static str = {component:?};\n");
+        let _ = writeln!(out, "    pub fn new(bus: &'b mut {krate}::HLAPIBus<T>, device: {krate}::types::HLAPIDeviceHandle) -> Self {{ Self {{ bus, device }} }}\n");
+        let _ = writeln!(out, "    /// First device having the component");
+        let _ = writeln!(out, "    pub fn find(bus: &'b mut {krate}::HLAPIBus<T>) -> {krate}::error::HLAPIResult<Self> {{");
+        let _ = writeln!(out, "        let device = bus.find(Self::COMPONENT)?;");
+        let _ = writeln!(out, "        Ok(Self::new(bus, device))");
+        let _ = writeln!(out, "    }}\n");
+        let _ = writeln!(out, "    pub fn device(&self) -> {krate}::types::HLAPIDeviceHandle {{ self.device }}");
+        let _ = writeln!(out, "    pub fn bus(&mut self) -> &mut {krate}::HLAPIBus<T> {{ self.bus }}");
+        let _ = writeln!(out, "}}\n");
+
+        let _ = writeln!(out, "impl<T: {krate}::transport::Transport> {name} for {client}<'_, T> {{");
+        for (rust, method) in &methods {
+            let arguments: Vec<String> = self.parameters(method).into_iter().map(|(argument, _)| argument).collect();
+            let arguments = match arguments.len() {
+                0 => format!("{krate}::types::EMPTY"),
+                1 => format!("({},)", arguments[0]),
+                _ => format!("({})", arguments.join(", ")),
+            };
+            let _ = writeln!(out, "    fn {} {{", self.method_head(rust, method));
+            if method.return_java_type().is_void() {
+                let _ = writeln!(out, "        self.bus.raw_call::<_, _, {krate}::types::Void>(self.device, {:?}, {arguments}).map(drop)", method.name);
+            } else {
+                let _ = writeln!(out, "        self.bus.raw_call(self.device, {:?}, {arguments})", method.name);
+            }
+            let _ = writeln!(out, "    }}");
+        }
+        let _ = writeln!(out, "}}");
+    }
+
+    /// Names and types, unnamed parameters being `arg0`, `arg1`...
+    fn parameters(&self, method: &HLAPIMethod) -> Vec<(String, String)> {
+        method.parameters.iter().enumerate().map(|(index, parameter)| {
+            let name = parameter.name.as_deref().map_or_else(|| format!("arg{index}"), identifier);
+            (name, self.rust_type(&parameter.java_type(), true))
+        }).collect()
+    }
+
+    fn method_head(&self, rust: &str, method: &HLAPIMethod) -> String {
+        let mut head = format!("{rust}(&mut self");
+        for (name, rust_type) in self.parameters(method) { let _ = write!(head, ", {name}: {rust_type}"); }
+        let _ = write!(head, ") -> {}::error::HLAPIResult<{}>", self.crate_path, self.rust_type(&method.return_java_type(), false));
+        head
+    }
+
+    fn method_doc(&self, out: &mut String, method: &HLAPIMethod) {
+        if let Some(description) = &method.description { doc(out, "    ", description); doc(out, "    ", ""); }
+        for (parameter, (name, _)) in method.parameters.iter().zip(self.parameters(method)) {
+            if let Some(description) = &parameter.description { doc(out, "    ", &format!("- `{}`: {description}", name.trim_start_matches("r#"))); }
+        }
+        if let Some(description) = &method.return_value_description { doc(out, "    ", &format!("Returns {description}")); }
+        doc(out, "    ", &format!("`{}`", signature::signature(method)));
+    }
+}
+
+impl Default for Generator {
+    fn default() -> Self { Self::new() }
+}
@@ -0,0 +1,85 @@
+use crate::types::*;
+use crate::signature::{self, JsonShape, SignatureError};
+
+/// Method of a `#[hlapi_component]` trait, as declared on the Rust side
+#[derive(Clone, Copy, Debug)]
+pub struct DeclaredMethod {
+    pub name: &'static str, // Java side name
+    pub parameters: &'static [&'static str], // Rust types, as written
+    pub returns: &'static str, // `()` for void, `HLAPIResult` stripped
+}
+
+impl DeclaredMethod {
+    /// `getRedstoneInput(Side): i32`
+    pub fn signature(&self) -> String { format!("{}({}): {}", self.name, self.parameters.join(", "), self.returns) }
+}
+
+/// What a Rust type looks like once serialized, `Any` for anything unknown (custom types, `HLAPIValue`...)
+pub fn rust_shape(rust: &str) -> JsonShape {
+    let rust: String = rust.chars().filter(|character| !character.is_whitespace()).collect();
+    let rust = rust.trim_start_matches('&');
+    let rust = match rust.strip_prefix('\'') { // lifetime
+        Some(lifetime) => lifetime.trim_start_matches(|character: char| character.is_alphanumeric() || character == '_'),
+        None => rust,
+    };
+    let rust = rust.strip_prefix("mut").filter(|rest| !rest.starts_with(|character: char| character.is_alphanumeric() || character == '_')).unwrap_or(rust);
+
+    if let Some(inner) = rust.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) { // slice or array
+        return JsonShape::Array(Box::new(rust_shape(inner.split(';').next().unwrap_or(inner))));
+    }
+    if let Some(inner) = rust.strip_prefix('(').and_then(|rest| rest.strip_suffix(')')) {
+        return if inner.is_empty() { JsonShape::Null } else { JsonShape::Array(Box::new(JsonShape::Any)) };
+    }
+    let (head, generics) = match rust.split_once('<') {
+        Some((head, generics)) => (head, generics.strip_suffix('>')),
+        None => (rust, None),
+    };
+    let first = || generics.map_or(JsonShape::Any, |generics| rust_shape(generics.split(',').next().unwrap_or(generics)));
+
+    match head.rsplit("::").next().unwrap_or(head) {
+        "bool" => JsonShape::Bool,
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128" | "usize" => JsonShape::Integer,
+        "f32" | "f64" => JsonShape::Number,
+        "char" | "str" | "String" | "Cow" => JsonShape::String,
+        "Option" | "Box" => first(),
+        "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => JsonShape::Array(Box::new(first())),
+        "HashMap" | "BTreeMap" | "Map" => JsonShape::Object,
+        _ => JsonShape::Any,
+    }
+}
+
+/// Whether values of the `sent` shape fit where `accepted` is expected
+pub fn compatible(sent: &JsonShape, accepted: &JsonShape) -> bool {
+    match (sent, accepted) {
+        (JsonShape::Any, _) | (_, JsonShape::Any) => true,
+        (JsonShape::Integer, JsonShape::Number) => true,
+        (JsonShape::Array(sent), JsonShape::Array(accepted)) => compatible(sent, accepted),
+        _ => sent == accepted,
+    }
+}
+
+/// Whether `method` can serve `declared`, a unit return ignoring whatever gets returned
+pub fn fits(declared: &DeclaredMethod, method: &HLAPIMethod) -> bool {
+    method.name == declared.name
+        && method.parameters.len() == declared.parameters.len()
+        && declared.parameters.iter().zip(method.parameter_types()).all(|(rust, java)| compatible(&rust_shape(rust), &java.json_shape()))
+        && (declared.returns == "()" || compatible(&method.return_java_type().json_shape(), &rust_shape(declared.returns)))
+}
+
+/// Checks every declared method against the ones of a device, fails on the first not fitting any overload
+pub fn verify(methods: &[HLAPIMethod], declared: &[DeclaredMethod]) -> Result<(), SignatureError> {
+    for declaration in declared {
+        let overloads: Vec<&HLAPIMethod> = methods.iter().filter(|method| method.name == declaration.name).collect();
+        if overloads.iter().any(|method| fits(declaration, method)) { continue; }
+
+        Err(SignatureError {
+            method: declaration.name.to_owned(),
+            signatures: overloads.iter().map(|method| signature::signature(method)).collect(),
+            reason: match overloads.is_empty() {
+                true => "no such method".to_owned(),
+                false => format!("declared as {}", declaration.signature()),
+            },
+        })?
+    }
+    Ok(())
+}
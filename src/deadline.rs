@@ -0,0 +1,132 @@
+use std::io::{Result as IOResult, Error as IOError, ErrorKind as IOErrorKind, Read};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::transport::Transport;
+use crate::error::HLAPIError;
+
+/// How often a blocked call checks for cancellation
+const CANCEL_POLL: Duration = Duration::from_millis(20);
+
+/// Shareable flag aborting the call currently blocked on a bus, from another thread or a signal handler
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) { self.0.store(true, Ordering::SeqCst); }
+    pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::SeqCst) }
+
+    /// Clears a cancellation, done as the next call starts, it stays set until then so that the rest of the packet isn't waited for
+    pub(crate) fn clear(&self) { self.0.store(false, Ordering::SeqCst); }
+}
+
+pub(crate) fn timed_out() -> IOError { IOError::new(IOErrorKind::TimedOut, "no answer from the HLAPI bus before the deadline") }
+/// Carries the `HLAPIError` itself, `Interrupted` being a plain `EINTR` to retry
+pub(crate) fn cancelled() -> IOError { IOError::other(HLAPIError::Cancelled) }
+
+/// Waits for the transport to be readable before the deadline, checking for cancellation in between
+pub(crate) fn wait<T: Transport + ?Sized>(transport: &mut T, deadline: Option<Instant>, cancel: Option<&CancelHandle>) -> IOResult<()> {
+    loop {
+        if cancel.is_some_and(CancelHandle::is_cancelled) { Err(cancelled())? }
+
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::ZERO) { Err(timed_out())? }
+
+        let slice = match cancel {
+            Some(_) => Some(remaining.map_or(CANCEL_POLL, |remaining| remaining.min(CANCEL_POLL))),
+            None => remaining,
+        };
+        match transport.wait_readable(slice) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(error) if error.kind() == IOErrorKind::Interrupted => {}
+            Err(error) => Err(error)?,
+        }
+    }
+}
+
+/// Reader waiting on the transport before every read, fails with `TimedOut` once the deadline passed
+pub(crate) struct DeadlineReader<'a, T: Transport> {
+    pub transport: &'a mut T,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<&'a CancelHandle>,
+}
+
+impl<T: Transport> Read for DeadlineReader<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        loop {
+            wait(self.transport, self.deadline, self.cancel)?;
+            match self.transport.read(buf) {
+                Err(error) if error.kind() == IOErrorKind::Interrupted => {}
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+    use crate::transport::MemoryTransport;
+    use crate::error::HLAPIError;
+    use crate::types::{HLAPIDeviceHandle, EMPTY};
+    use crate::{HLAPIBus, DELIM};
+
+    const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    /// Answers every request sent from now on with the next packet of `replies`, until the bus gets dropped
+    fn serve(mut server: MemoryTransport, replies: &'static [&'static str]) -> JoinHandle<()> {
+        let mut sent = vec![0; server.pending()];
+        server.read_exact(&mut sent).unwrap();
+        thread::spawn(move || {
+            let (mut replies, mut pending, mut bytes) = (replies.iter(), 0, [0; 256]);
+            loop {
+                let count = server.read(&mut bytes).unwrap();
+                if count == 0 { return; }
+                for &byte in &bytes[..count] {
+                    if byte != DELIM[0] { pending += 1; continue; }
+                    if std::mem::take(&mut pending) == 0 { continue; } // reset
+                    if let Some(reply) = replies.next() { write!(server, "\0{reply}\0").unwrap(); }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn timed_out_answers_get_drained() {
+        let (client, mut server) = MemoryTransport::pair();
+        let mut bus = HLAPIBus::new(client);
+        bus.set_timeout(Some(Duration::from_millis(50)));
+        assert!(matches!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY), Err(HLAPIError::Timeout)));
+
+        write!(server, "\0{{\"type\":\"result\",\"data\":1}}\0").unwrap(); // the late answer
+        let _server = serve(server, &[r#"{"type":"result","data":2}"#]);
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY).unwrap(), 2);
+    }
+
+    #[test]
+    fn cancels_blocked_calls() {
+        let (client, server) = MemoryTransport::pair();
+        let mut bus = HLAPIBus::new(client);
+        let cancel = bus.cancel_handle();
+        let canceller = thread::spawn(move || { thread::sleep(Duration::from_millis(50)); cancel.cancel(); });
+        assert!(matches!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY), Err(HLAPIError::Cancelled)));
+        canceller.join().unwrap();
+
+        let _server = serve(server, &[r#"{"type":"result","data":2}"#]);
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY).unwrap(), 2);
+    }
+
+    #[test]
+    fn cancels_within_a_packet() {
+        let (client, mut server) = MemoryTransport::pair();
+        let mut bus = HLAPIBus::new(client);
+        let cancel = bus.cancel_handle();
+        write!(server, "\0{{\"type\":\"result\",\"data\":[1,").unwrap();
+        let canceller = thread::spawn(move || { thread::sleep(Duration::from_millis(50)); cancel.cancel(); });
+        assert!(matches!(bus.raw_call::<_, _, Vec<i32>>(DEVICE, "get", EMPTY), Err(HLAPIError::Cancelled)));
+        canceller.join().unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use std::borrow::Cow;
+use serde::de::DeserializeOwned;
+use crate::types::*;
+use crate::error::*;
+use crate::args::HLAPIArgs;
+use crate::transport::Transport;
+use crate::value::HLAPIValue;
+use crate::HLAPIBus;
+
+/// `set_redstone_output` to `setRedstoneOutput`, names without underscores are left as is
+pub fn camel_case(name: &str) -> Cow<'_, str> {
+    if !name.contains('_') { return Cow::Borrowed(name); }
+
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for character in name.chars() {
+        match character {
+            '_' => upper = !camel.is_empty(),
+            _ if upper => { camel.extend(character.to_uppercase()); upper = false; }
+            _ => camel.push(character),
+        }
+    }
+    Cow::Owned(camel)
+}
+
+/// `setRedstoneOutput` to `set_redstone_output`
+pub fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (index, character) in name.char_indices() {
+        if character.is_uppercase() {
+            if index > 0 { snake.push('_'); }
+            snake.extend(character.to_lowercase());
+        } else { snake.push(character); }
+    }
+    snake
+}
+
+/// What a device is and what it can do, without the bus, see `Device`
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub descriptor: HLAPIDeviceDescriptor,
+    pub methods: Vec<HLAPIMethod>,
+}
+
+impl DeviceInfo {
+    pub fn id(&self) -> HLAPIDeviceHandle { self.descriptor.device_id }
+    pub fn components(&self) -> &[String] { &self.descriptor.components }
+    pub fn has_component(&self, name: &str) -> bool { self.descriptor.components.iter().any(|component| component == name) }
+    pub fn methods(&self) -> &[HLAPIMethod] { &self.methods }
+
+    /// Java side name of a method, given either as is or in snake_case
+    pub fn method_name<'n>(&self, name: &'n str) -> Option<Cow<'n, str>> {
+        if self.methods.iter().any(|method| method.name == name) { return Some(Cow::Borrowed(name)); }
+        let camel = camel_case(name);
+        self.methods.iter().any(|method| method.name == camel).then_some(camel)
+    }
+
+    pub fn has_method(&self, name: &str) -> bool { self.method_name(name).is_some() }
+
+    /// Every overload of a method
+    pub fn overloads<'i>(&'i self, name: &str) -> impl Iterator<Item = &'i HLAPIMethod> + 'i {
+        let name = self.method_name(name).map(Cow::into_owned);
+        self.methods.iter().filter(move |method| Some(&method.name) == name.as_ref())
+    }
+
+    pub fn bind<T: Transport>(self, bus: &mut HLAPIBus<T>) -> Device<'_, T> { Device { bus, info: self } }
+}
+
+/// Device bound to a bus, carrying its components and methods
+pub struct Device<'b, T: Transport> {
+    bus: &'b mut HLAPIBus<T>,
+    info: DeviceInfo,
+}
+
+impl<'b, T: Transport> Device<'b, T> {
+    /// Fetches the device methods, fails with `DeviceNotFound` if it isn't in the device list
+    pub fn new(bus: &'b mut HLAPIBus<T>, device: HLAPIDeviceHandle) -> HLAPIResult<Self> {
+        let descriptor = bus.list()?.into_iter().find(|descriptor| descriptor.device_id == device)
+            .ok_or_else(|| HLAPIError::DeviceNotFound(format!("id {device}")))?;
+        Self::from_descriptor(bus, descriptor)
+    }
+
+    pub fn from_descriptor(bus: &'b mut HLAPIBus<T>, descriptor: HLAPIDeviceDescriptor) -> HLAPIResult<Self> {
+        let methods = bus.methods(descriptor.device_id)?;
+        Ok(Self { bus, info: DeviceInfo { descriptor, methods } })
+    }
+
+    pub fn info(&self) -> &DeviceInfo { &self.info }
+    /// Releases the bus, see `DeviceInfo::bind` to get it back
+    pub fn into_info(self) -> DeviceInfo { self.info }
+    pub fn bus(&mut self) -> &mut HLAPIBus<T> { self.bus }
+
+    pub fn id(&self) -> HLAPIDeviceHandle { self.info.id() }
+    pub fn components(&self) -> &[String] { self.info.components() }
+    pub fn methods(&self) -> &[HLAPIMethod] { self.info.methods() }
+    pub fn has_method(&self, name: &str) -> bool { self.info.has_method(name) }
+
+    /// `device.call("set_redstone_output", (side, 15))`, names are mapped to camelCase when the device doesn't know them as is
+    pub fn call<InTuple: HLAPIArgs, OutTuple: DeserializeOwned>(&mut self, method: &str, args: InTuple) -> HLAPIResult<OutTuple> {
+        let name = self.info.method_name(method).unwrap_or_else(|| camel_case(method));
+        self.bus.raw_call(self.info.id(), name.as_ref(), args)
+    }
+
+    pub fn call_dynamic(&mut self, method: &str, args: Vec<HLAPIValue>) -> HLAPIResult<HLAPIValue> {
+        self.call::<_, Option<HLAPIValue>>(method, args).map(|value| value.unwrap_or(HLAPIValue::Null))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use crate::transport::MemoryTransport;
+    use super::*;
+
+    const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    #[test]
+    fn dynamic_calls_of_void_methods_return_null() {
+        let (client, mut server) = MemoryTransport::pair();
+        let mut bus = HLAPIBus::new(client);
+        write!(server, "\0{{\"type\":\"result\"}}\0\0{{\"type\":\"result\",\"data\":null}}\0\0{{\"type\":\"result\"}}\0\0{{\"type\":\"result\",\"data\":[1]}}\0").unwrap();
+
+        assert_eq!(bus.call_dynamic(DEVICE, "beep", vec![]).unwrap(), HLAPIValue::Null);
+        assert_eq!(bus.call_dynamic(DEVICE, "beep", vec![]).unwrap(), HLAPIValue::Null);
+        let mut device = DeviceInfo { descriptor: HLAPIDeviceDescriptor { device_id: DEVICE, components: vec![] }, methods: vec![] }.bind(&mut bus);
+        assert_eq!(device.call_dynamic("beep", vec![]).unwrap(), HLAPIValue::Null);
+        assert_eq!(device.call_dynamic("get", vec![]).unwrap(), HLAPIValue::Array(vec![HLAPIValue::Integer(1)]));
+    }
+}
@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+use std::io::Result as IOResult;
+use std::time::Duration;
+use crate::types::*;
+use crate::error::*;
+use crate::HLAPIBus;
+
+/// Console devices worth probing, as found in `/dev`
+pub const CANDIDATE_PREFIXES: &[&str] = &["hvc"];
+
+/// How long a candidate has to answer the `list` request
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Outcome of probing one console device
+#[derive(Debug)]
+pub struct BusProbe {
+    pub path: PathBuf,
+    pub result: HLAPIResult<Vec<HLAPIDeviceDescriptor>>, // devices seen trough it when it answered
+}
+
+impl BusProbe {
+    pub fn answers(&self) -> bool { self.result.is_ok() }
+}
+
+/// Every `/dev` entry starting with one of the `CANDIDATE_PREFIXES`, sorted
+pub fn candidates() -> IOResult<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir("/dev")? {
+        let entry = entry?;
+        if entry.file_name().to_str().is_some_and(|name| CANDIDATE_PREFIXES.iter().any(|prefix| name.starts_with(prefix))) {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Sends an HLAPI `list` request on `path`, and waits at most `timeout` for an answer
+pub fn probe(path: impl AsRef<Path>, timeout: Duration) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+    let mut bus = HLAPIBus::open(path)?;
+    bus.set_timeout(Some(timeout));
+    bus.reset()?; // whatever was left on the Java side
+    bus.list()
+}
+
+/// Probes every candidate, see `probe_paths`
+pub fn probe_buses(timeout: Duration) -> IOResult<Vec<BusProbe>> {
+    Ok(probe_paths(candidates()?, timeout))
+}
+
+pub fn probe_paths(paths: impl IntoIterator<Item = PathBuf>, timeout: Duration) -> Vec<BusProbe> {
+    paths.into_iter().map(|path| BusProbe { result: probe(&path, timeout), path }).collect()
+}
+
+/// Paths of the consoles answering HLAPI requests
+pub fn discover() -> IOResult<Vec<PathBuf>> {
+    Ok(probe_buses(PROBE_TIMEOUT)?.into_iter().filter(BusProbe::answers).map(|probe| probe.path).collect())
+}
@@ -0,0 +1,159 @@
+use std::fmt::{Display, Formatter};
+use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+use crate::types::*;
+use crate::signature::SignatureError;
+
+pub type HLAPIResult<T> = Result<T, HLAPIError>;
+
+#[derive(Debug)]
+pub enum HLAPIError {
+    /// The transport failed
+    Io(IOError),
+    /// No answer before the deadline, the bus got reset
+    Timeout,
+    /// The call got aborted trough a `CancelHandle`, the bus got reset
+    Cancelled,
+    /// The Java side answered with an error
+    Remote(RemoteError),
+    /// The Java side answered, but not with what the request calls for
+    UnexpectedResponse { expected: &'static str, received: &'static str },
+    /// The answer doesn't fit the expected type, `context` tells what was being read
+    Deserialize { context: String, source: serde_json::Error },
+    /// The request couldn't be encoded
+    Serialize(serde_json::Error),
+    /// The encoded request (delimiters included) doesn't fit in what the Java side accepts
+    Oversize { size: usize, limit: usize },
+    /// No device matches the query
+    DeviceNotFound(String),
+    /// Exactly one device was asked for, but several match the query
+    AmbiguousDevice { query: String, matches: Vec<HLAPIDeviceHandle> },
+    /// The arguments don't fit the method descriptors, caught before sending anything
+    InvalidArguments(SignatureError),
+}
+
+impl HLAPIError {
+    /// Error for an answer of the wrong kind, error answers become `Remote`
+    pub fn unexpected<Tuple>(expected: &'static str, received: HLAPIReceive<Tuple>) -> Self {
+        match received {
+            HLAPIReceive::Error(message) => Self::Remote(RemoteError::new(message)),
+            other => Self::UnexpectedResponse { expected, received: other.kind() },
+        }
+    }
+
+    pub(crate) fn deserialize(context: impl Display, source: serde_json::Error) -> Self {
+        if source.is_io() { Self::from(IOError::from(source)) } // the reader failed, not the data
+        else { Self::Deserialize { context: context.to_string(), source } }
+    }
+
+    pub fn remote_kind(&self) -> Option<RemoteErrorKind> {
+        if let Self::Remote(error) = self { Some(error.kind) } else { None }
+    }
+}
+
+impl Display for HLAPIError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "HLAPI transport error: {error}"),
+            Self::Timeout => f.write_str("HLAPI call timed out"),
+            Self::Cancelled => f.write_str("HLAPI call cancelled"),
+            Self::Remote(error) => write!(f, "HLAPI error: {error}"),
+            Self::UnexpectedResponse { expected, received } => write!(f, "expected a {expected} HLAPI response, received a {received} one"),
+            Self::Deserialize { context, source } => write!(f, "invalid {context}: {source}"),
+            Self::Serialize(error) => write!(f, "could not encode the HLAPI request: {error}"),
+            Self::Oversize { size, limit } => write!(f, "the HLAPI request takes {size} bytes, over the {limit} bytes limit"),
+            Self::DeviceNotFound(query) => write!(f, "no device matching {query}"),
+            Self::AmbiguousDevice { query, matches } => write!(f, "{} devices matching {query}", matches.len()),
+            Self::InvalidArguments(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for HLAPIError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Remote(error) => Some(error),
+            Self::InvalidArguments(error) => Some(error),
+            Self::Deserialize { source, .. } | Self::Serialize(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<IOError> for HLAPIError {
+    fn from(error: IOError) -> Self {
+        match error.kind() {
+            IOErrorKind::TimedOut => Self::Timeout,
+            _ if matches!(error.get_ref().and_then(|inner| inner.downcast_ref()), Some(Self::Cancelled)) => Self::Cancelled,
+            _ => Self::Io(error),
+        }
+    }
+}
+
+impl From<SignatureError> for HLAPIError {
+    fn from(error: SignatureError) -> Self { Self::InvalidArguments(error) }
+}
+
+impl From<RemoteError> for HLAPIError {
+    fn from(error: RemoteError) -> Self { Self::Remote(error) }
+}
+
+/// Lets `HLAPIResult`s go trough `std::io` based code
+impl From<HLAPIError> for IOError {
+    fn from(error: HLAPIError) -> Self {
+        match error {
+            HLAPIError::Io(error) => error,
+            HLAPIError::Timeout => IOError::new(IOErrorKind::TimedOut, error),
+            HLAPIError::Cancelled => IOError::other(error), // not `Interrupted`, that callers would retry
+            HLAPIError::DeviceNotFound(_) => IOError::new(IOErrorKind::NotFound, error),
+            HLAPIError::Oversize { .. } => IOError::new(IOErrorKind::WriteZero, error),
+            other => IOError::new(IOErrorKind::InvalidData, other),
+        }
+    }
+}
+
+/// What the Java side complained about, recognized from its error message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoteErrorKind {
+    MessageTooLarge,
+    UnknownMessageType,
+    UnknownDevice,
+    UnknownMethod,
+    /// Wrong argument count or argument types not matching, the Java side doesn't tell them apart
+    InvalidSignature,
+    /// Anything else, usually thrown by the method itself
+    Other,
+}
+
+impl RemoteErrorKind {
+    pub fn classify(message: &str) -> Self {
+        match message {
+            ERROR_MESSAGE_BUFFER_OVERFLOW => Self::MessageTooLarge,
+            ERROR_UNKNOWN_MESSAGE_TYPE => Self::UnknownMessageType,
+            ERROR_UNKNOWN_DEVICE => Self::UnknownDevice,
+            ERROR_UNKNOWN_METHOD => Self::UnknownMethod,
+            ERROR_INVALID_PARAMETER_SIGNATURE => Self::InvalidSignature,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RemoteError {
+    pub kind: RemoteErrorKind,
+    pub message: Option<String>, // as sent by the Java side
+}
+
+impl RemoteError {
+    pub fn new(message: Option<String>) -> Self {
+        Self { kind: message.as_deref().map_or(RemoteErrorKind::Other, RemoteErrorKind::classify), message }
+    }
+}
+
+impl Display for RemoteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message.as_deref().unwrap_or("unknown error"))
+    }
+}
+
+impl std::error::Error for RemoteError { }
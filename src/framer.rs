@@ -0,0 +1,200 @@
+use std::io::{Result as IOResult, ErrorKind as IOErrorKind, BufRead, Read};
+use std::ops::Range;
+use crate::DELIM;
+
+const DELIM_BYTE: u8 = DELIM[0];
+
+/// Skips everything up to the beginning of the next packet (past its opening delimiter), returns the amount of garbage skipped
+/// Extra delimiters (empty packets, resets, closing delimiter of a previous packet) are not counted as garbage
+pub fn begin<R: BufRead + ?Sized>(reader: &mut R) -> IOResult<usize> {
+    let (mut skipped, mut opened) = (0, false);
+    loop {
+        let available = reader.fill_buf()?;
+        if available.is_empty() { Err(IOErrorKind::UnexpectedEof)? }
+
+        let mut consumed = 0;
+        let mut found = false;
+        for &byte in available {
+            if byte == DELIM_BYTE { opened = true; }
+            else if opened { found = true; break; }
+            else { skipped += 1; }
+            consumed += 1;
+        }
+        reader.consume(consumed);
+        if found { return Ok(skipped); }
+    }
+}
+
+/// Location of the first complete packet within already buffered bytes, see `find_frame`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub skipped: usize, // garbage before the opening delimiter
+    pub payload: Range<usize>,
+    pub end: usize, // past the closing delimiter
+}
+
+/// Same resynchronization as `begin`, but over bytes already in memory, `None` until a whole packet is there
+pub fn find_frame(bytes: &[u8]) -> Option<Frame> {
+    let opening = bytes.iter().position(|&byte| byte == DELIM_BYTE)?;
+    let start = opening + bytes[opening..].iter().position(|&byte| byte != DELIM_BYTE)?;
+    let length = bytes[start..].iter().position(|&byte| byte == DELIM_BYTE)?;
+    Some(Frame { skipped: opening, payload: start..start + length, end: start + length + 1 })
+}
+
+/// Reads the content of a single packet, EOF being its closing delimiter
+pub struct FrameReader<'r, R: BufRead + ?Sized> {
+    inner: &'r mut R,
+    done: bool,
+}
+
+impl<'r, R: BufRead + ?Sized> FrameReader<'r, R> {
+    /// Expects the opening delimiter to be already consumed, see `begin`
+    pub fn new(inner: &'r mut R) -> Self { Self { inner, done: false } }
+
+    pub fn is_done(&self) -> bool { self.done }
+
+    /// Consumes the rest of the packet up to and including its closing delimiter, returns the amount of bytes discarded
+    /// Call it even after a failed deserialization, so that the next read starts on a packet boundary
+    pub fn finish(mut self) -> IOResult<usize> {
+        let mut discarded = 0;
+        while !self.done {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() { Err(IOErrorKind::UnexpectedEof)? }
+            match available.iter().position(|&byte| byte == DELIM_BYTE) {
+                Some(end) => {
+                    self.inner.consume(end + 1);
+                    discarded += end;
+                    self.done = true;
+                }
+                None => {
+                    let count = available.len();
+                    self.inner.consume(count);
+                    discarded += count;
+                }
+            }
+        }
+        Ok(discarded)
+    }
+}
+
+impl<R: BufRead + ?Sized> Read for FrameReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        if self.done || buf.is_empty() { return Ok(0); }
+
+        let available = self.inner.fill_buf()?;
+        if available.is_empty() { Err(IOErrorKind::UnexpectedEof)? } // the packet got cut
+
+        let end = available.iter().position(|&byte| byte == DELIM_BYTE);
+        let count = end.unwrap_or(available.len()).min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+
+        if end == Some(count) { // reached the closing delimiter
+            self.inner.consume(count + 1);
+            self.done = true;
+        } else { self.inner.consume(count); }
+        Ok(count)
+    }
+}
+
+/// Lets the packet be peeked at, e.g. to pull its JSON content piece by piece
+impl<R: BufRead + ?Sized> BufRead for FrameReader<'_, R> {
+    fn fill_buf(&mut self) -> IOResult<&[u8]> {
+        if self.done { return Ok(&[]); }
+
+        let available = self.inner.fill_buf()?;
+        if available.is_empty() { Err(IOErrorKind::UnexpectedEof)? }
+        if available[0] == DELIM_BYTE {
+            self.inner.consume(1);
+            self.done = true;
+            return Ok(&[]);
+        }
+
+        let available = self.inner.fill_buf()?;
+        let end = available.iter().position(|&byte| byte == DELIM_BYTE).unwrap_or(available.len());
+        Ok(&available[..end])
+    }
+
+    /// Never past what `fill_buf` returned, so never past the closing delimiter
+    fn consume(&mut self, amount: usize) { self.inner.consume(amount); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::mock::{MockDevice, MockTransport, MockBus};
+    use crate::error::HLAPIError;
+    use crate::types::{HLAPIDeviceHandle, EMPTY};
+
+    const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    fn bus() -> MockBus {
+        MockBus::new(MockTransport::new().with_device(MockDevice::new(DEVICE, ["counter"]).method("get", &[], "int", |_| Ok(json!(42)))))
+    }
+
+    #[test]
+    fn begin_skips_garbage_but_not_delimiters() {
+        let mut input: &[u8] = b"noise\0\0\0[1]\0";
+        assert_eq!(begin(&mut input).unwrap(), 5);
+        assert_eq!(input, b"[1]\0");
+    }
+
+    #[test]
+    fn begin_fails_without_packet() {
+        let mut input: &[u8] = b"noise\0\0";
+        assert_eq!(begin(&mut input).unwrap_err().kind(), IOErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn frame_reader_stops_on_the_closing_delimiter() {
+        let mut input: &[u8] = b"\0{\"a\":1}\0rest";
+        begin(&mut input).unwrap();
+        let mut frame = FrameReader::new(&mut input);
+        let mut payload = String::new();
+        frame.read_to_string(&mut payload).unwrap();
+        assert_eq!(payload, "{\"a\":1}");
+        assert!(frame.is_done());
+        assert_eq!(input, b"rest");
+    }
+
+    #[test]
+    fn frame_reader_fails_on_cut_packets() {
+        let mut input: &[u8] = b"\0[1,";
+        begin(&mut input).unwrap();
+        let error = FrameReader::new(&mut input).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), IOErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn finish_discards_the_rest_of_the_packet() {
+        let mut input: &[u8] = b"\0[1, 2]\0\0[3]\0";
+        begin(&mut input).unwrap();
+        let mut frame = FrameReader::new(&mut input);
+        frame.read_exact(&mut [0; 2]).unwrap();
+        assert_eq!(frame.finish().unwrap(), 4);
+        assert_eq!(input, b"\0[3]\0");
+    }
+
+    #[test]
+    fn find_frame_locates_whole_packets_only() {
+        assert_eq!(find_frame(b"ab\0\0[1]\0rest"), Some(Frame { skipped: 2, payload: 4..7, end: 8 }));
+        assert_eq!(find_frame(b"ab\0\0[1"), None);
+        assert_eq!(find_frame(b"\0\0"), None);
+    }
+
+    #[test]
+    fn bus_counts_skipped_garbage() {
+        let mut bus = bus();
+        bus.transport_mut().inject_bytes(b"garbage\0\0");
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY).unwrap(), 42);
+        assert_eq!(bus.skipped_bytes(), 7);
+    }
+
+    #[test]
+    fn bus_resynchronizes_after_a_failed_deserialization() {
+        let mut bus = bus();
+        let error = bus.raw_call::<_, _, String>(DEVICE, "get", EMPTY).unwrap_err();
+        assert!(matches!(error, HLAPIError::Deserialize { .. }), "{error:?}");
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY).unwrap(), 42);
+    }
+}
@@ -8,16 +8,51 @@
 #![allow(clippy::needless_question_mark)]
 
 pub mod types;
-
-use std::fmt::{Display, Formatter};
+pub mod transport;
+pub mod mock;
+pub mod deadline;
+pub mod framer;
+pub mod buffer;
+pub mod error;
+#[cfg(feature = "tokio")] pub mod async_bus;
+pub mod discovery;
+pub mod terminal;
+pub mod signature;
+pub mod cache;
+pub mod value;
+pub mod device;
+pub mod query;
+pub mod watcher;
+pub mod trace;
+pub mod replay;
+pub mod stream;
+pub mod codegen;
+pub mod component;
+pub mod schema;
+pub mod args;
+
+pub use oc2devices_macros::{hlapi_component, HLAPIArgs};
+pub use args::HLAPIArgs;
+
+/// Used by the generated code
+#[doc(hidden)]
+pub mod __private { pub use serde; }
+
+use std::fmt::Display;
 use types::*;
-
-use std::fs::File;
-use std::os::unix::io::AsRawFd;
-use std::io::{Result as IOResult, Error as IOError, ErrorKind as IOErrorKind, Write, Read};
-use epoll_rs::{Epoll, Opts as PollOpts};
-use serde::{ser::Serialize, de::{ Deserialize, DeserializeOwned, Visitor, SeqAccess, Error}};
-use stack_buffer::{StackBufReader};
+use transport::*;
+use deadline::{CancelHandle, DeadlineReader};
+use framer::FrameReader;
+use buffer::ReadBuffer;
+use error::*;
+use cache::DeviceCache;
+use query::DeviceQuery;
+use trace::{TraceWriter, TracingTransport};
+use stream::ResultIter;
+
+use std::io::{Result as IOResult, Write};
+use std::time::{Duration, Instant};
+use serde::{ser::Serialize, de::{Deserialize, DeserializeOwned}};
 use arrayvec::ArrayVec;
 
 /// Used as the delimiter for HLAPI JSON packets
@@ -27,154 +62,405 @@ pub const DELIM: &[u8] = b"\0";
 const READ_BUF: usize = 4096; // TODO: Benchmark different sizes trough file importing
 
 /// Maximum size for sending buffers, limitation from OC2 VMs to Java
-const MAX_WRITE: usize = 4096; // TODO: try using buffers and benchmark
+pub const MAX_WRITE: usize = 4096; // TODO: try using buffers and benchmark
+
+/// How long to wait for late answers when cleaning up after a timeout
+const DRAIN_GRACE: Duration = Duration::from_millis(50);
+
+/// Size of the packet sending `request` would take, delimiters included, to be checked against `MAX_WRITE`
+pub fn encoded_size<Name: AsRef<str> + Serialize, Tuple: Serialize>(request: &HLAPISend<Name, Tuple>) -> HLAPIResult<usize> {
+    struct Counter(usize);
+    impl Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> { self.0 += buf.len(); Ok(buf.len()) }
+        fn flush(&mut self) -> IOResult<()> { Ok(()) }
+    }
+
+    let mut counter = Counter(2 * DELIM.len());
+    serde_json::to_writer(&mut counter, request).map_err(HLAPIError::Serialize)?;
+    Ok(counter.0)
+}
 
-/// Main bus path
-const MAIN_BUS: &str = "/dev/hvc0";
+/// Largest `n` in `1..=max` for which `fits(n)` holds, `fits` being monotonic, 0 if none does
+fn largest_fitting(max: usize, mut fits: impl FnMut(usize) -> HLAPIResult<bool>) -> HLAPIResult<usize> {
+    let (mut low, mut high) = (0, max); // fits(low) holds, or low is 0
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        if fits(middle)? { low = middle; } else { high = middle - 1; }
+    }
+    Ok(low)
+}
+
+/// Packet ready to be sent, delimiters included, shared by the blocking and async buses
+pub(crate) fn encode<Name: AsRef<str> + Serialize, Tuple: Serialize>(data: &HLAPISend<Name, Tuple>) -> HLAPIResult<ArrayVec<u8, MAX_WRITE>> {
+    let mut buffer = ArrayVec::<u8, MAX_WRITE>::new();
+    let oversize = || match encoded_size(data) { // only measured when it doesn't fit
+        Ok(size) => HLAPIError::Oversize { size, limit: MAX_WRITE },
+        Err(error) => error,
+    };
+
+    // ArrayVec yields ErrorKind::WriteZero if we're writing more than it can handle
+    buffer.write_all(DELIM).map_err(|_| oversize())?;
+    serde_json::to_writer(&mut buffer, data).map_err(|error| if error.is_io() { oversize() } else { HLAPIError::Serialize(error) })?;
+    buffer.write_all(DELIM).map_err(|_| oversize())?;
 
-pub struct HLAPIBus {
-    handle: File,
-    poller: Epoll,
+    Ok(buffer)
 }
 
-impl HLAPIBus {
+/// Decodes a whole packet content, see `framer::find_frame`
+#[cfg(feature = "tokio")]
+pub(crate) fn decode<OutTuple: DeserializeOwned>(payload: &[u8], context: impl Display) -> HLAPIResult<HLAPIReceive<OutTuple>> {
+    serde_json::from_slice(payload).map_err(|error| HLAPIError::deserialize(context, error))
+}
+
+pub struct HLAPIBus<T: Transport = HvcTransport> {
+    handle: T,
+    timeout: Option<Duration>, // None waits forever
+    deadline: Option<Instant>, // of the call in progress
+    cancel: Option<CancelHandle>,
+    stale: bool, // a late answer from a timed out call may still come
+    skipped: usize, // garbage bytes dropped while looking for packet boundaries
+    buffer: ReadBuffer, // survives across calls, may already hold the next packets
+    cache: Option<DeviceCache>,
+}
+
+impl HLAPIBus<HvcTransport> {
     pub fn main_bus() -> IOResult<Self> {
-        let poller = Epoll::new()?;
-        let handle = poller.add(File::options().read(true).write(true).open(MAIN_BUS)?, PollOpts::IN)?.into_file();
+        Ok(Self::new(HvcTransport::main_bus()?))
+    }
+
+    /// Bus on another console device than `/dev/hvc0`, see `discovery::probe_buses` to find them
+    pub fn open(path: impl AsRef<std::path::Path>) -> IOResult<Self> {
+        Ok(Self::new(HvcTransport::open(path)?))
+    }
+
+    /// Same as `open` with custom tty settings, restored when the bus gets dropped
+    pub fn open_with(path: impl AsRef<std::path::Path>, config: &terminal::TtyConfig) -> IOResult<Self> {
+        Ok(Self::new(HvcTransport::open_with(path, config)?))
+    }
+
+    /// Bus over an inherited descriptor, e.g. passed in by a supervisor
+    pub fn from_file(file: std::fs::File) -> IOResult<Self> {
+        Ok(Self::new(HvcTransport::from_file(file)?))
+    }
+
+    /// # Safety
+    /// `descriptor` must be an open file descriptor owned by nobody else
+    pub unsafe fn from_raw_fd(descriptor: std::os::unix::io::RawFd) -> IOResult<Self> {
+        Ok(Self::new(HvcTransport::from_raw_fd(descriptor)?))
+    }
+}
+
+impl<T: Transport> HLAPIBus<T> {
+    /// Speaks HLAPI over any transport (pipes, sockets, in-memory queues...)
+    pub fn new(handle: T) -> Self {
+        Self { handle, timeout: None, deadline: None, cancel: None, stale: false, skipped: 0, buffer: ReadBuffer::new(READ_BUF), cache: None }
+    }
 
-        let descriptor = handle.as_raw_fd();
-        let mut termios = termios::Termios::from_fd(descriptor)?;
+    /// Default timeout for every call, a timed out call returns `HLAPIError::Timeout` and resets the bus
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) { self.timeout = timeout; }
+    pub fn timeout(&self) -> Option<Duration> { self.timeout }
 
-        termios::cfmakeraw(&mut termios); // raw
-        termios.c_lflag &= !termios::ECHO; // -echo
-        termios::tcsetattr(descriptor, termios::TCSANOW, &termios)?; // immediate flush
+    /// Overrides the timeout for the calls made within `call`, e.g. `bus.with_timeout(Some(delay), |bus| bus.list())`
+    pub fn with_timeout<R>(&mut self, timeout: Option<Duration>, call: impl FnOnce(&mut Self) -> R) -> R {
+        let default = std::mem::replace(&mut self.timeout, timeout);
+        let result = call(self);
+        self.timeout = default;
+        result
+    }
+
+    /// Handle that makes the blocked call return `HLAPIError::Cancelled`, the bus gets reset the same way as on timeout
+    /// Only the call in progress gets cancelled, the next one starts afresh
+    pub fn cancel_handle(&mut self) -> CancelHandle {
+        self.cancel.get_or_insert_with(CancelHandle::default).clone()
+    }
+
+    /// Keeps `list` and `methods` results, for `ttl` or until invalidated (`None` until then)
+    /// Calls failing with an unknown device error invalidate that device automatically
+    pub fn enable_cache(&mut self, ttl: Option<Duration>) { self.cache = Some(DeviceCache::new(ttl)); }
+    pub fn disable_cache(&mut self) { self.cache = None; }
+    pub fn cache(&self) -> Option<&DeviceCache> { self.cache.as_ref() }
+
+    pub fn invalidate_cache(&mut self) {
+        if let Some(cache) = &mut self.cache { cache.invalidate(); }
+    }
+
+    pub fn invalidate_device(&mut self, device: HLAPIDeviceHandle) {
+        if let Some(cache) = &mut self.cache { cache.invalidate_device(device); }
+    }
+
+    /// A device that went away isn't worth remembering
+    fn forget_unknown<V>(&mut self, device: HLAPIDeviceHandle, result: HLAPIResult<V>) -> HLAPIResult<V> {
+        if let Err(error) = &result {
+            if error.remote_kind() == Some(RemoteErrorKind::UnknownDevice) { self.invalidate_device(device); }
+        }
+        result
+    }
 
-        termios::cfsetspeed(&mut termios, termios::B38400)?; // baud 38400 // TODO: try faster BAUD rates
+    /// Total amount of bytes found outside of packets or after their JSON content, and thrown away to resynchronize
+    pub fn skipped_bytes(&self) -> usize { self.skipped }
+
+    pub fn transport(&self) -> &T { &self.handle }
+    pub fn transport_mut(&mut self) -> &mut T { &mut self.handle }
+    pub fn into_transport(self) -> T { self.handle }
+
+    /// Tees every packet to a JSONL trace, e.g. `HLAPIBus::main_bus()?.traced(TraceWriter::create(TraceConfig::new("hlapi.jsonl"))?)`
+    pub fn traced(self, writer: TraceWriter) -> HLAPIBus<TracingTransport<T>> {
+        let Self { handle, timeout, deadline, cancel, stale, skipped, buffer, cache } = self;
+        HLAPIBus { handle: TracingTransport::new(handle, writer), timeout, deadline, cancel, stale, skipped, buffer, cache }
+    }
 
-        Ok(Self { handle, poller })
+    pub fn list(&mut self) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        if let Some(devices) = self.cache.as_ref().and_then(DeviceCache::list) { return Ok(devices.to_vec()); }
+        self.refresh_list()
     }
 
-    pub fn list(&mut self) -> IOResult<Vec<HLAPIDeviceDescriptor>> {
+    /// `list` bypassing the cache, which gets updated
+    pub fn refresh_list(&mut self) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
         self.write::<&'static str, Empty>(&HLAPISend::List)?;
-        let list: HLAPIReceive = self.read()?;
-        if let HLAPIReceive::List(devices) = list {
-            Ok(devices)
-        } else { Err(IOErrorKind::InvalidData.into()) }
+        match self.read::<Void>("device list")? {
+            HLAPIReceive::List(devices) => {
+                if let Some(cache) = &mut self.cache { cache.store_list(devices.clone()); }
+                Ok(devices)
+            }
+            other => Err(HLAPIError::unexpected("list", other)),
+        }
     }
 
-    pub fn methods(&mut self, device: HLAPIDeviceHandle) -> IOResult<Vec<HLAPIMethod>> {
-        self.write::<&'static str, Empty>(&HLAPISend::Methods(device))?;
-        let list: HLAPIReceive = self.read()?;
-        if let HLAPIReceive::Methods(methods) = list {
-            Ok(methods)
-        } else { Err(IOErrorKind::InvalidData.into()) }
+    pub fn methods(&mut self, device: HLAPIDeviceHandle) -> HLAPIResult<Vec<HLAPIMethod>> {
+        if let Some(methods) = self.cache.as_ref().and_then(|cache| cache.methods(device)) { return Ok(methods.to_vec()); }
+
+        let result = (|| -> HLAPIResult<Vec<HLAPIMethod>> {
+            self.write::<&'static str, Empty>(&HLAPISend::Methods(device))?;
+            match self.read::<Void>("method list")? {
+                HLAPIReceive::Methods(methods) => Ok(methods),
+                other => Err(HLAPIError::unexpected("methods", other)),
+            }
+        })();
+        if let (Ok(methods), Some(cache)) = (&result, &mut self.cache) { cache.store_methods(device, methods.clone()); }
+        self.forget_unknown(device, result)
     }
 
-    pub fn find(&mut self, name: &str) -> IOResult<HLAPIDeviceHandle> {
+    pub fn find(&mut self, name: &str) -> HLAPIResult<HLAPIDeviceHandle> {
         for HLAPIDeviceDescriptor { device_id, components } in self.list()? {
             if components.into_iter().any(|dev| name == dev) { return Ok(device_id); }
         }
-        Err(IOErrorKind::NotFound.into())
+        Err(HLAPIError::DeviceNotFound(format!("component {name}")))
+    }
+
+    /// Every device having the `name` component
+    pub fn find_all(&mut self, name: &str) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        self.query(&DeviceQuery::new().component(name))
+    }
+
+    /// Device by full UUID or unique prefix of it
+    pub fn find_by_id(&mut self, prefix: &str) -> HLAPIResult<HLAPIDeviceDescriptor> {
+        self.query_one(&DeviceQuery::new().id(prefix))
+    }
+
+    /// Every device having all of the components
+    pub fn find_with_components(&mut self, names: &[&str]) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        self.query(&DeviceQuery::new().components(names.iter().copied()))
+    }
+
+    /// Every device having all of the methods
+    pub fn find_with_methods(&mut self, names: &[&str]) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        self.query(&DeviceQuery::new().methods(names.iter().copied()))
+    }
+
+    pub fn filter(&mut self, mut predicate: impl FnMut(&HLAPIDeviceDescriptor) -> bool) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        Ok(self.list()?.into_iter().filter(|descriptor| predicate(descriptor)).collect())
+    }
+
+    /// Every device matching the query, methods are only fetched for devices matching everything else
+    pub fn query(&mut self, query: &DeviceQuery) -> HLAPIResult<Vec<HLAPIDeviceDescriptor>> {
+        let mut found = Vec::new();
+        for descriptor in self.list()? {
+            if !query.matches_descriptor(&descriptor) { continue; }
+            if query.needs_methods() && !query.matches_methods(&self.methods(descriptor.device_id)?) { continue; }
+            found.push(descriptor);
+        }
+        Ok(found)
+    }
+
+    /// The only device matching the query, `AmbiguousDevice` if there are several
+    pub fn query_one(&mut self, query: &DeviceQuery) -> HLAPIResult<HLAPIDeviceDescriptor> {
+        let mut found = self.query(query)?;
+        match found.len() {
+            0 => Err(HLAPIError::DeviceNotFound(query.to_string())),
+            1 => Ok(found.remove(0)),
+            _ => Err(HLAPIError::AmbiguousDevice { query: query.to_string(), matches: found.iter().map(|descriptor| descriptor.device_id).collect() }),
+        }
     }
 
-    pub fn raw_call<Name: AsRef<str> + Serialize, InTuple: Serialize, OutTuple: DeserializeOwned>
-    (&mut self, device: HLAPIDeviceHandle, method: Name, args: InTuple) -> IOResult<OutTuple> {
+    /// Proxy carrying the device components and methods
+    pub fn device(&mut self, device: HLAPIDeviceHandle) -> HLAPIResult<device::Device<'_, T>> {
+        device::Device::new(self, device)
+    }
+
+    /// Proxy of the first device having the `name` component, see `find`
+    pub fn find_device(&mut self, name: &str) -> HLAPIResult<device::Device<'_, T>> {
+        let descriptor = self.list()?.into_iter().find(|descriptor| descriptor.components.iter().any(|dev| name == dev))
+            .ok_or_else(|| HLAPIError::DeviceNotFound(format!("component {name}")))?;
+        device::Device::from_descriptor(self, descriptor)
+    }
+
+    pub fn raw_call<Name: AsRef<str> + Serialize, InTuple: HLAPIArgs, OutTuple: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: Name, args: InTuple) -> HLAPIResult<OutTuple> {
         self.write(&HLAPISend::Invoke {
             device_id: device,
-            method_name: method,
+            method_name: method.as_ref(),
             parameters: args,
         })?;
-        Ok(self.read()?.expect_result().ok_or(IOErrorKind::InvalidData)?)
-    }
-
-    pub fn raw_call_streamed<Name: AsRef<str> + Serialize, InTuple: Serialize, OutItem: DeserializeOwned, Function: FnMut(OutItem) -> Result<(), FnError>, FnError>
-    (&mut self, device: HLAPIDeviceHandle, method: Name, args: InTuple, function: &mut Function) -> IOResult<usize> {
-
-        struct Nothing;
-        impl Display for Nothing { fn fmt(&self, _f: &mut Formatter<'_>) -> std::fmt::Result { Ok(()) } }
-        fn stream<'de, Deserializer: serde::Deserializer<'de>, Item: Deserialize<'de>, E>(deserializer: Deserializer, function: impl FnMut(Item) -> Result<(), E>) -> Result<usize, Deserializer::Error> {
-            struct StreamingVisitor<Fn, T>(usize, Fn, std::marker::PhantomData<fn(T)>);
-
-            impl<'de, Item: Deserialize<'de>, E, Fn: FnMut(Item) -> Result<(), E>> Visitor<'de> for StreamingVisitor<Fn, Item> {
-                type Value = usize;
-
-                fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
-                    // Error: invalid type: ..., expected
-                    formatter.write_str("a sequence of ")?;
-                    formatter.write_str(std::any::type_name::<Item>())?;
-                    formatter.write_str("'s")?;
-                    Ok(())
-                }
-
-                fn visit_unit<E>(self) -> Result<Self::Value, E> { // In case of a None
-                    Ok(self.0) // assert self.0 == 0
-                }
-
-                fn visit_seq<S: SeqAccess<'de>>(mut self, mut seq: S) -> Result<Self::Value, S::Error> {
-                    while let Some(item) = seq.next_element()? {
-                        self.0 += 1;
-                        (self.1)(item).map_err(|_| S::Error::custom(Nothing))?;
-                    }
-                    Ok(self.0)
-                }
+        let result = match self.read(format_args!("result of {}", method.as_ref())) {
+            Ok(HLAPIReceive::Result(tuple)) => Ok(tuple),
+            Ok(other) => Err(HLAPIError::unexpected("result", other)),
+            Err(error) => Err(error),
+        };
+        self.forget_unknown(device, result)
+    }
+
+    /// Untyped `raw_call`, for when the types are only known at runtime, `void` methods returning `Null`
+    pub fn call_dynamic(&mut self, device: HLAPIDeviceHandle, method: &str, args: Vec<value::HLAPIValue>) -> HLAPIResult<value::HLAPIValue> {
+        // Gson leaves `data` out when null
+        self.raw_call::<_, _, Option<value::HLAPIValue>>(device, method, args).map(|value| value.unwrap_or(value::HLAPIValue::Null))
+    }
+
+    /// Same as `raw_call`, but checks `args` against the method descriptors first (arity, JSON kinds, overloads)
+    /// and fails locally with `HLAPIError::InvalidArguments` naming the expected signatures
+    pub fn validated_call<InTuple: HLAPIArgs, OutTuple: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: &str, args: InTuple) -> HLAPIResult<OutTuple> {
+        let args = serde_json::to_value(args).map_err(HLAPIError::Serialize)?;
+        let methods = self.methods(device)?;
+        let args = signature::positional(&args);
+        signature::resolve(&methods, method, args)?;
+        self.raw_call(device, method, args)
+    }
+
+    /// Size of the packet this call would send, see `encoded_size`
+    pub fn encoded_call_size<InTuple: HLAPIArgs>(&self, device: HLAPIDeviceHandle, method: &str, args: InTuple) -> HLAPIResult<usize> {
+        encoded_size(&HLAPISend::Invoke { device_id: device, method_name: method, parameters: args })
+    }
+
+    /// Calls `method` as many times as needed for `data` to go trough in requests under `MAX_WRITE`, `args` building the parameters out of each chunk
+    /// e.g. `bus.raw_call_chunked(card, "write", &bytes, |chunk| (chunk.to_vec(),))`, returns the result of every call
+    /// The parameters can't borrow the chunk, `InTuple` being the same type for every chunk
+    pub fn raw_call_chunked<Item: Serialize, InTuple: HLAPIArgs, OutTuple: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: &str, data: &[Item], mut args: impl FnMut(&[Item]) -> InTuple) -> HLAPIResult<Vec<OutTuple>> {
+        let (mut results, mut rest) = (Vec::new(), data);
+        while !rest.is_empty() {
+            let length = largest_fitting(rest.len(), |length| Ok(self.encoded_call_size(device, method, args(&rest[..length]))? <= MAX_WRITE))?;
+            if length == 0 { // not even a single item fits
+                Err(HLAPIError::Oversize { size: self.encoded_call_size(device, method, args(&rest[..1]))?, limit: MAX_WRITE })?
             }
+            let (chunk, remaining) = rest.split_at(length);
+            results.push(self.raw_call(device, method, args(chunk))?);
+            rest = remaining;
+        }
+        Ok(results)
+    }
 
-            // Create the visitor and ask the deserializer to drive it. The
-            // deserializer will call visitor.visit_seq() if a seq is present in
-            // the input data.
-            let visitor = StreamingVisitor(0, function, std::marker::PhantomData);
-            deserializer.deserialize_seq(visitor)
+    /// Same as `raw_call_chunked` for long strings, split on char boundaries, e.g. `|chunk| (path, chunk.to_owned())`
+    pub fn raw_call_chunked_str<InTuple: HLAPIArgs, OutTuple: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: &str, text: &str, mut args: impl FnMut(&str) -> InTuple) -> HLAPIResult<Vec<OutTuple>> {
+        fn floor_boundary(text: &str, mut index: usize) -> usize {
+            while !text.is_char_boundary(index) { index -= 1; }
+            index
         }
 
+        let (mut results, mut rest) = (Vec::new(), text);
+        while !rest.is_empty() {
+            let length = largest_fitting(rest.len(), |length| Ok(self.encoded_call_size(device, method, args(&rest[..floor_boundary(rest, length)]))? <= MAX_WRITE))?;
+            let length = floor_boundary(rest, length);
+            if length == 0 {
+                let first = rest.chars().next().map_or(0, char::len_utf8);
+                Err(HLAPIError::Oversize { size: self.encoded_call_size(device, method, args(&rest[..first]))?, limit: MAX_WRITE })?
+            }
+            let (chunk, remaining) = rest.split_at(length);
+            results.push(self.raw_call(device, method, args(chunk))?);
+            rest = remaining;
+        }
+        Ok(results)
+    }
+
+    /// Pull-based iterator over the elements of an array result, read as they come in
+    /// e.g. `for file in bus.raw_call_iter::<_, _, String>(disk, "list", ("/",))? { ... }`, a `null` result yields nothing
+    pub fn raw_call_iter<Name: AsRef<str> + Serialize, InTuple: HLAPIArgs, OutItem: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: Name, args: InTuple) -> HLAPIResult<ResultIter<'_, T, OutItem>> {
+        self.raw_call_iter_at(device, method, args, &[])
+    }
+
+    /// Same as `raw_call_iter`, over an array nested in the result: `path` holds object keys, or indexes when going trough arrays
+    /// e.g. `&["inventory", "slots"]` streams the slots of `{"inventory": {"size": 27, "slots": [...]}}`
+    pub fn raw_call_iter_at<Name: AsRef<str> + Serialize, InTuple: HLAPIArgs, OutItem: DeserializeOwned>
+    (&mut self, device: HLAPIDeviceHandle, method: Name, args: InTuple, path: &[&str]) -> HLAPIResult<ResultIter<'_, T, OutItem>> {
         self.write(&HLAPISend::Invoke {
             device_id: device,
-            method_name: method,
+            method_name: method.as_ref(),
             parameters: args,
         })?;
+        ResultIter::open(self, device, method.as_ref(), path)
+    }
 
-        self.poller.wait_one()?;
-        let mut buffer = StackBufReader::<_, READ_BUF>::new(&mut self.handle);
+    /// Calls `function` on every element of an array result, stopping on its first error, which is given back as is
+    /// Returns the amount of elements processed, the rest of the packet gets skipped either way
+    pub fn raw_call_streamed<Name: AsRef<str> + Serialize, InTuple: HLAPIArgs, OutItem: DeserializeOwned, FnError: From<HLAPIError>>
+    (&mut self, device: HLAPIDeviceHandle, method: Name, args: InTuple, mut function: impl FnMut(OutItem) -> Result<(), FnError>) -> Result<usize, FnError> {
+        let mut items = self.raw_call_iter(device, method, args)?;
+        for item in &mut items {
+            function(item?)?;
+        }
+        items.close()?;
+        Ok(items.streamed())
+    }
 
-        Self::check_delim(&mut buffer)?;
+    /// `context` describes what's being read for deserialization errors
+    fn read<OutTuple: DeserializeOwned>(&mut self, context: impl Display) -> HLAPIResult<HLAPIReceive<OutTuple>> {
+        let result = (|| -> HLAPIResult<HLAPIReceive<OutTuple>> {
+            let mut reader = DeadlineReader { transport: &mut self.handle, deadline: self.deadline, cancel: self.cancel.as_ref() };
+            let mut buffer = self.buffer.reader(&mut reader);
 
-        let count = stream(&mut serde_json::Deserializer::from_reader(&mut buffer), function).map_err::<IOError, _>(|_| IOErrorKind::InvalidData.into())?;
+            let mut skipped = framer::begin(&mut buffer)?;
+            let mut frame = FrameReader::new(&mut buffer);
 
-        // Don't forget to check for delim only after using up the iterator
-        Self::check_delim(&mut buffer)?;
+            let data = HLAPIReceive::<OutTuple>::deserialize(&mut serde_json::Deserializer::from_reader(&mut frame));
 
-        Ok(count)
-    }
+            // Resynchronizes on the closing delimiter, even after a failed deserialization
+            skipped += frame.finish()?;
+            self.skipped += skipped;
 
-    fn check_delim<R: Read>(buffer: &mut R) -> IOResult<()> {
-        let mut delim_buf = [0; DELIM.len()];
-        let bytes_read = buffer.read(&mut delim_buf)?;
-        if bytes_read != DELIM.len() || delim_buf != DELIM {
-            Err(IOErrorKind::UnexpectedEof)?
-        } else { Ok(()) }
+            Ok(data.map_err(|error| HLAPIError::deserialize(context, error))?)
+        })();
+        self.recover(result)
     }
 
-    fn read<OutTuple: DeserializeOwned>(&mut self) -> IOResult<HLAPIReceive<OutTuple>> {
-        self.poller.wait_one()?;
-        let mut buffer = StackBufReader::<_, READ_BUF>::new(&mut self.handle);
-
-        Self::check_delim(&mut buffer)?;
-
-        let mut deserializer = serde_json::Deserializer::from_reader(&mut buffer);
-        let data = HLAPIReceive::<OutTuple>::deserialize(&mut deserializer)?;
-
-        Self::check_delim(&mut buffer)?;
+    /// The answer of a timed out or cancelled call may still come later: reset the Java side, and drop it before the next call
+    fn recover<V>(&mut self, result: HLAPIResult<V>) -> HLAPIResult<V> {
+        if let Err(HLAPIError::Timeout | HLAPIError::Cancelled) = &result {
+            self.reset()?;
+            self.stale = true;
+        }
+        result
+    }
 
-        Ok(data)
+    /// Discards everything received until the bus stays quiet for `grace`, returns the amount of bytes dropped
+    pub fn drain(&mut self, grace: Duration) -> IOResult<usize> {
+        let (mut discarded, mut sink) = (self.buffer.clear(), [0; READ_BUF]);
+        while self.handle.wait_readable(Some(grace))? {
+            let count = self.handle.read(&mut sink)?;
+            if count == 0 { break; } // EOF
+            discarded += count;
+        }
+        self.stale = false;
+        Ok(discarded)
     }
 
-    /// Throws ErrorKind::WriteZero if the message is over 4kB (absolute limit for sending from VM to Java)
-    fn write<Name: AsRef<str> + Serialize, Tuple: Serialize>(&mut self, data: &HLAPISend<Name, Tuple>) -> IOResult<()> {
-        let mut buffer = ArrayVec::<u8, MAX_WRITE>::new();
+    /// Throws HLAPIError::Oversize with the actual size if the message is over 4kB (absolute limit for sending from VM to Java)
+    fn write<Name: AsRef<str> + Serialize, Tuple: Serialize>(&mut self, data: &HLAPISend<Name, Tuple>) -> HLAPIResult<()> {
+        if self.stale { self.drain(DRAIN_GRACE)?; }
+        if let Some(cancel) = &self.cancel { cancel.clear(); }
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
 
-        // Yields IOErrorKind::WriteZero if we're writing more than the buffer can handle
-        buffer.write_all(DELIM)?;
-        serde_json::to_writer(&mut buffer, data).map_err::<IOError, _>(|_| IOErrorKind::InvalidData.into())?;
-        buffer.write_all(DELIM)?;
+        let buffer = encode(data)?;
 
         // Does not write to the socket, unless the buffer is not overflown, so no need to handle the WriteZero error and flush the bus
         self.handle.write_all(&buffer)?;
@@ -189,4 +475,73 @@ impl HLAPIBus {
         self.handle.flush()?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use crate::mock::{MockDevice, MockTransport, MockBus};
+    use super::*;
+
+    const CARD: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    fn bus() -> MockBus {
+        let card = MockDevice::new(CARD, ["card"])
+            .method("write", &["[B"], "int", |args| Ok(json!(args[0].as_array().map_or(0, Vec::len))))
+            .method("append", &["java.lang.String", "java.lang.String"], "int", |args| Ok(json!(args[1].as_str().map_or(0, str::len))));
+        MockBus::new(MockTransport::new().with_device(card))
+    }
+
+    /// Parameters of every request sent, and checks they all fit
+    fn sent(bus: &MockBus) -> Vec<Vec<Value>> {
+        bus.transport().received().iter().map(|request| {
+            assert!(encoded_size(request).unwrap() <= MAX_WRITE);
+            match request {
+                HLAPISend::Invoke { parameters, .. } => parameters.as_array().unwrap().clone(),
+                _ => Vec::new(),
+            }
+        }).collect()
+    }
+
+    #[test]
+    fn largest_fitting_finds_the_boundary() {
+        assert_eq!(largest_fitting(100, |n| Ok(n <= 37)).unwrap(), 37);
+        assert_eq!(largest_fitting(100, |n| Ok(n <= 100)).unwrap(), 100);
+        assert_eq!(largest_fitting(100, |_| Ok(false)).unwrap(), 0);
+        assert_eq!(largest_fitting(0, |_| Ok(true)).unwrap(), 0);
+    }
+
+    #[test]
+    fn chunks_data_under_the_write_limit() {
+        let mut bus = bus();
+        let bytes: Vec<u8> = (0..10_000).map(|index| (index % 251) as u8).collect();
+        let written: Vec<usize> = bus.raw_call_chunked(CARD, "write", &bytes, |chunk| (chunk.to_vec(),)).unwrap();
+        assert!(written.len() > 2);
+        assert_eq!(written.iter().sum::<usize>(), bytes.len());
+
+        let sent: Vec<u8> = sent(&bus).iter().flat_map(|parameters| parameters[0].as_array().unwrap().iter().map(|byte| byte.as_u64().unwrap() as u8)).collect();
+        assert_eq!(sent, bytes);
+        assert!(bus.raw_call_chunked::<u8, _, usize>(CARD, "write", &[], |chunk| (chunk.to_vec(),)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn chunks_strings_on_char_boundaries() {
+        let mut bus = bus();
+        let text = "déjà vu, ".repeat(1_000);
+        let written: Vec<usize> = bus.raw_call_chunked_str(CARD, "append", &text, |chunk| ("notes.txt", chunk.to_owned())).unwrap();
+        assert!(written.len() > 2);
+        let sent: String = sent(&bus).iter().map(|parameters| parameters[1].as_str().unwrap()).collect();
+        assert_eq!(sent, text);
+    }
+
+    #[test]
+    fn fails_when_a_single_item_does_not_fit() {
+        let mut bus = bus();
+        let path = "x".repeat(MAX_WRITE);
+        let error = bus.raw_call_chunked_str::<_, usize>(CARD, "append", "text", |chunk| (path.as_str(), chunk.to_owned())).unwrap_err();
+        assert!(matches!(error, HLAPIError::Oversize { size, limit: MAX_WRITE } if size > MAX_WRITE), "{error:?}");
+        let error = bus.raw_call::<_, _, usize>(CARD, "write", (vec![0u8; MAX_WRITE],)).unwrap_err();
+        assert!(matches!(error, HLAPIError::Oversize { .. }), "{error:?}");
+        assert!(bus.transport().received().is_empty());
+    }
+}
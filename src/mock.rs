@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::io::{Result as IOResult, ErrorKind as IOErrorKind, Write, Read};
+use std::time::Duration;
+use serde_json::Value;
+use crate::types::*;
+use crate::transport::Transport;
+use crate::signature::positional;
+use crate::{HLAPIBus, DELIM};
+
+/// Body of a fake method, receives the positional arguments, `Err` is sent back as an HLAPI error
+pub type MockMethodBody = Box<dyn FnMut(&[Value]) -> Result<Value, String>>;
+
+/// A fake device served by the `MockTransport`
+pub struct MockDevice {
+    pub descriptor: HLAPIDeviceDescriptor,
+    methods: Vec<(HLAPIMethod, MockMethodBody)>,
+}
+
+impl MockDevice {
+    pub fn new<S: Into<String>>(device_id: HLAPIDeviceHandle, components: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            descriptor: HLAPIDeviceDescriptor { device_id, components: components.into_iter().map(Into::into).collect() },
+            methods: Vec::new(),
+        }
+    }
+
+    /// Registers a method, parameter and return types are the Java names (`"int"`, `"java.lang.String"`, `"void"`...)
+    pub fn method(self, name: &str, parameters: &[&str], return_type: &str, body: impl FnMut(&[Value]) -> Result<Value, String> + 'static) -> Self {
+        self.method_descriptor(HLAPIMethod {
+            name: name.to_owned(),
+            parameters: parameters.iter().map(|&ty| HLAPIType::new(ty)).collect(),
+            return_type: return_type.to_owned(),
+            description: None,
+            return_value_description: None,
+        }, body)
+    }
+
+    pub fn method_descriptor(mut self, descriptor: HLAPIMethod, body: impl FnMut(&[Value]) -> Result<Value, String> + 'static) -> Self {
+        self.methods.push((descriptor, Box::new(body)));
+        self
+    }
+
+    fn invoke(&mut self, name: &str, args: &[Value]) -> HLAPIReceive<Value> {
+        if !self.methods.iter().any(|(method, _)| method.name == name) {
+            return HLAPIReceive::Error(Some(ERROR_UNKNOWN_METHOD.to_owned()));
+        }
+        // Overloads are told apart by their arity only
+        match self.methods.iter_mut().find(|(method, _)| method.name == name && method.parameters.len() == args.len()) {
+            Some((_, body)) => match body(args) {
+                Ok(value) => HLAPIReceive::Result(value),
+                Err(message) => HLAPIReceive::Error(Some(message)),
+            },
+            None => HLAPIReceive::Error(Some(ERROR_INVALID_PARAMETER_SIGNATURE.to_owned())),
+        }
+    }
+}
+
+/// In-process HLAPI server speaking the same JSON protocol as the Java side, answering synchronously on write
+#[derive(Default)]
+pub struct MockTransport {
+    devices: Vec<MockDevice>,
+    injected: VecDeque<HLAPIReceive<Value>>,
+    received: Vec<HLAPISend<String, Value>>,
+    pending: Vec<u8>, // incoming packet being assembled
+    outgoing: VecDeque<u8>,
+}
+
+pub type MockBus = HLAPIBus<MockTransport>;
+
+impl MockTransport {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn with_device(mut self, device: MockDevice) -> Self {
+        self.add_device(device);
+        self
+    }
+
+    pub fn add_device(&mut self, device: MockDevice) { self.devices.push(device); }
+
+    pub fn remove_device(&mut self, device_id: HLAPIDeviceHandle) -> Option<MockDevice> {
+        let index = self.devices.iter().position(|device| device.descriptor.device_id == device_id)?;
+        Some(self.devices.remove(index))
+    }
+
+    /// The next request gets answered with this error instead of being processed
+    pub fn inject_error(&mut self, message: impl Into<String>) {
+        self.injected.push_back(HLAPIReceive::Error(Some(message.into())));
+    }
+
+    /// The next request gets answered with this response instead of being processed
+    pub fn inject_response(&mut self, response: HLAPIReceive<Value>) {
+        self.injected.push_back(response);
+    }
+
+    /// Raw bytes sent as-is to the client, useful to simulate garbage on the line
+    pub fn inject_bytes(&mut self, bytes: &[u8]) { self.outgoing.extend(bytes); }
+
+    /// Every request successfully decoded so far
+    pub fn received(&self) -> &[HLAPISend<String, Value>] { &self.received }
+
+    fn respond(&mut self, packet: &[u8]) -> HLAPIReceive<Value> {
+        let request = match serde_json::from_slice::<HLAPISend<String, Value>>(packet) {
+            Ok(request) => request,
+            Err(_) => return HLAPIReceive::Error(Some(ERROR_UNKNOWN_MESSAGE_TYPE.to_owned())),
+        };
+        self.received.push(request.clone());
+        if let Some(response) = self.injected.pop_front() { return response; }
+
+        match request {
+            HLAPISend::List => HLAPIReceive::List(self.devices.iter().map(|device| device.descriptor.clone()).collect()),
+            HLAPISend::Methods(device_id) => match self.devices.iter().find(|device| device.descriptor.device_id == device_id) {
+                Some(device) => HLAPIReceive::Methods(device.methods.iter().map(|(method, _)| method.clone()).collect()),
+                None => HLAPIReceive::Error(Some(ERROR_UNKNOWN_DEVICE.to_owned())),
+            },
+            HLAPISend::Invoke { device_id, method_name, parameters } => {
+                match self.devices.iter_mut().find(|device| device.descriptor.device_id == device_id) {
+                    Some(device) => device.invoke(&method_name, positional(&parameters)),
+                    None => HLAPIReceive::Error(Some(ERROR_UNKNOWN_DEVICE.to_owned())),
+                }
+            }
+        }
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let count = buf.len().min(self.outgoing.len());
+        for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) { *slot = byte; }
+        Ok(count)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        for &byte in buf {
+            if byte != DELIM[0] { self.pending.push(byte); continue; }
+            if self.pending.is_empty() { continue; } // opening delimiter or reset
+
+            let packet = std::mem::take(&mut self.pending);
+            let response = self.respond(&packet);
+            self.outgoing.extend(DELIM);
+            serde_json::to_writer(&mut self.outgoing, &response)?;
+            self.outgoing.extend(DELIM);
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IOResult<()> { Ok(()) }
+}
+
+impl Transport for MockTransport {
+    /// Everything is answered on write, so waiting on an empty queue without timeout would block forever
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> {
+        match (self.outgoing.is_empty(), timeout) {
+            (false, _) => Ok(true),
+            (true, Some(timeout)) => { std::thread::sleep(timeout); Ok(false) } // nothing can come in the meantime
+            (true, None) => Err(std::io::Error::new(IOErrorKind::WouldBlock, "the mock bus has nothing left to answer")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::error::{HLAPIError, RemoteErrorKind};
+
+    const REDSTONE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+    const UNKNOWN: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(2);
+
+    fn bus() -> MockBus {
+        let device = MockDevice::new(REDSTONE, ["redstone"])
+            .method("getRedstoneInput", &["java.lang.String"], "int", |args| Ok(json!(args[0].as_str().map_or(0, str::len))))
+            .method("getRedstoneInput", &[], "int", |_| Ok(json!(-1)))
+            .method("fail", &[], "void", |_| Err("broken".to_owned()));
+        MockBus::new(MockTransport::new().with_device(device))
+    }
+
+    fn remote_kind<V>(result: Result<V, HLAPIError>) -> Option<RemoteErrorKind> { result.err().and_then(|error| error.remote_kind()) }
+
+    #[test]
+    fn lists_devices_and_methods() {
+        let mut bus = bus();
+        let devices = bus.list().unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!((devices[0].device_id, devices[0].components.as_slice()), (REDSTONE, &["redstone".to_owned()][..]));
+        assert_eq!(bus.methods(REDSTONE).unwrap().len(), 3);
+        assert_eq!(remote_kind(bus.methods(UNKNOWN)), Some(RemoteErrorKind::UnknownDevice));
+    }
+
+    #[test]
+    fn invokes_overloads_by_arity() {
+        let mut bus = bus();
+        assert_eq!(bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", ("up",)).unwrap(), 2);
+        assert_eq!(bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", EMPTY).unwrap(), -1);
+        assert_eq!(remote_kind(bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", ("up", 1))), Some(RemoteErrorKind::InvalidSignature));
+    }
+
+    #[test]
+    fn reports_remote_errors() {
+        let mut bus = bus();
+        assert_eq!(remote_kind(bus.raw_call::<_, _, Void>(REDSTONE, "missing", EMPTY)), Some(RemoteErrorKind::UnknownMethod));
+        assert_eq!(remote_kind(bus.raw_call::<_, _, Void>(UNKNOWN, "fail", EMPTY)), Some(RemoteErrorKind::UnknownDevice));
+        assert_eq!(remote_kind(bus.raw_call::<_, _, Void>(REDSTONE, "fail", EMPTY)), Some(RemoteErrorKind::Other));
+    }
+
+    #[test]
+    fn injected_responses_come_first() {
+        let mut bus = bus();
+        bus.transport_mut().inject_error(ERROR_MESSAGE_BUFFER_OVERFLOW);
+        bus.transport_mut().inject_response(HLAPIReceive::Result(json!(7)));
+        assert_eq!(remote_kind(bus.list()), Some(RemoteErrorKind::MessageTooLarge));
+        assert_eq!(bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", EMPTY).unwrap(), 7);
+        assert_eq!(bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", EMPTY).unwrap(), -1);
+    }
+
+    #[test]
+    fn records_requests() {
+        let mut bus = bus();
+        bus.list().unwrap();
+        bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", ("up",)).unwrap();
+        assert!(matches!(bus.transport().received(), [
+            HLAPISend::List,
+            HLAPISend::Invoke { device_id: REDSTONE, method_name, parameters },
+        ] if method_name == "getRedstoneInput" && *parameters == json!(["up"])));
+    }
+
+    #[test]
+    fn removed_devices_become_unknown() {
+        let mut bus = bus();
+        assert!(bus.transport_mut().remove_device(REDSTONE).is_some());
+        assert!(bus.list().unwrap().is_empty());
+        assert_eq!(remote_kind(bus.raw_call::<_, _, i32>(REDSTONE, "getRedstoneInput", EMPTY)), Some(RemoteErrorKind::UnknownDevice));
+    }
+}
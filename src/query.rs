@@ -0,0 +1,79 @@
+use std::fmt::{Display, Formatter};
+use crate::types::*;
+
+type Predicate = Box<dyn Fn(&HLAPIDeviceDescriptor) -> bool>;
+
+/// Requirements a device must meet, see `HLAPIBus::query`
+/// e.g. `DeviceQuery::new().component("redstone").method("getRedstoneInput")`
+#[derive(Default)]
+pub struct DeviceQuery {
+    id_prefix: Option<String>,
+    components: Vec<String>,
+    methods: Vec<String>,
+    predicates: Vec<Predicate>,
+}
+
+impl DeviceQuery {
+    pub fn new() -> Self { Self::default() }
+
+    /// Full UUID, or any unique prefix of it, hyphenated or not
+    pub fn id(mut self, prefix: impl Into<String>) -> Self {
+        self.id_prefix = Some(prefix.into().to_lowercase());
+        self
+    }
+
+    /// Required component, may be given several times
+    pub fn component(mut self, name: impl Into<String>) -> Self {
+        self.components.push(name.into());
+        self
+    }
+
+    pub fn components<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.components.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    /// Required method, needs a `methods` request per candidate device
+    pub fn method(mut self, name: impl Into<String>) -> Self {
+        self.methods.push(name.into());
+        self
+    }
+
+    pub fn methods<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.methods.extend(names.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn filter(mut self, predicate: impl Fn(&HLAPIDeviceDescriptor) -> bool + 'static) -> Self {
+        self.predicates.push(Box::new(predicate));
+        self
+    }
+
+    pub fn needs_methods(&self) -> bool { !self.methods.is_empty() }
+
+    /// Everything but the methods
+    pub fn matches_descriptor(&self, descriptor: &HLAPIDeviceDescriptor) -> bool {
+        self.id_prefix.as_ref().is_none_or(|prefix| {
+            descriptor.device_id.to_string().starts_with(prefix.as_str()) || descriptor.device_id.simple().to_string().starts_with(prefix.as_str())
+        })
+            && self.components.iter().all(|name| descriptor.components.contains(name))
+            && self.predicates.iter().all(|predicate| predicate(descriptor))
+    }
+
+    pub fn matches_methods(&self, methods: &[HLAPIMethod]) -> bool {
+        self.methods.iter().all(|name| methods.iter().any(|method| &method.name == name))
+    }
+}
+
+impl Display for DeviceQuery {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut requirements = Vec::new();
+        if let Some(prefix) = &self.id_prefix { requirements.push(format!("id {prefix}")); }
+        requirements.extend(self.components.iter().map(|name| format!("component {name}")));
+        requirements.extend(self.methods.iter().map(|name| format!("method {name}")));
+        if !self.predicates.is_empty() { requirements.push(format!("{} filters", self.predicates.len())); }
+
+        if requirements.is_empty() { f.write_str("any device") }
+        else { f.write_str(&requirements.join(", ")) }
+    }
+}
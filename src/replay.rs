@@ -0,0 +1,324 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{Result as IOResult, Error as IOError, ErrorKind as IOErrorKind, Write, Read};
+use std::path::Path;
+use std::time::Duration;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::trace::{TraceRecord, TraceDirection, Splitter};
+use crate::transport::Transport;
+use crate::{HLAPIBus, DELIM};
+
+/// How closely the client packets must follow the recorded ones
+#[derive(Clone, Debug, Default)]
+pub struct ReplayConfig {
+    pub ignore_uuids: bool, // any UUID string matches any other
+    pub float_tolerance: Option<f64>, // absolute, applies when either number isn't an integer
+    pub ignore_paths: Vec<String>, // e.g. `data.parameters[0]`, see `Divergence::path`
+}
+
+impl ReplayConfig {
+    pub fn strict() -> Self { Self::default() }
+
+    /// UUIDs ignored and floats within 1e-6
+    pub fn lenient() -> Self { Self { ignore_uuids: true, float_tolerance: Some(1e-6), ignore_paths: Vec::new() } }
+
+    pub fn ignore_path(mut self, path: impl Into<String>) -> Self {
+        self.ignore_paths.push(path.into());
+        self
+    }
+}
+
+/// First mismatch between the client and the recording
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub packet: usize, // index of the client packet, from 0
+    pub record: usize, // index in the trace
+    pub path: String, // where in the JSON, empty for the whole packet
+    pub expected: Option<String>, // None past the end of the trace
+    pub received: String,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "packet {} diverges from trace record {}", self.packet, self.record)?;
+        if !self.path.is_empty() { write!(f, " at {}", self.path)?; }
+        match &self.expected {
+            Some(expected) => write!(f, ": expected {expected}, received {}", self.received),
+            None => write!(f, ": trace ended, received {}", self.received),
+        }
+    }
+}
+
+impl std::error::Error for Divergence { }
+
+/// `path`, where `expected` and `received` differ, if they do
+fn compare(config: &ReplayConfig, path: &mut String, expected: &Value, received: &Value) -> Option<(String, Value, Value)> {
+    if config.ignore_paths.iter().any(|ignored| ignored == path) { return None; }
+    let differ = || Some((path.clone(), expected.clone(), received.clone()));
+
+    match (expected, received) {
+        (Value::String(left), Value::String(right)) if config.ignore_uuids && Uuid::parse_str(left).is_ok() && Uuid::parse_str(right).is_ok() => None,
+        (Value::Number(left), Value::Number(right)) if left != right => {
+            let tolerance = config.float_tolerance.filter(|_| left.is_f64() || right.is_f64());
+            match (tolerance, left.as_f64(), right.as_f64()) {
+                (Some(tolerance), Some(left), Some(right)) if (left - right).abs() <= tolerance => None,
+                _ => differ(),
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            if left.len() != right.len() { return differ(); }
+            left.iter().zip(right).enumerate().find_map(|(index, (left, right))| {
+                let length = path.len();
+                path.push_str(&format!("[{index}]"));
+                let found = compare(config, path, left, right);
+                path.truncate(length);
+                found
+            })
+        }
+        (Value::Object(left), Value::Object(right)) => {
+            if left.len() != right.len() || left.keys().any(|key| !right.contains_key(key)) { return differ(); }
+            left.iter().find_map(|(key, left)| {
+                let length = path.len();
+                if !path.is_empty() { path.push('.'); }
+                path.push_str(key);
+                let found = compare(config, path, left, &right[key]);
+                path.truncate(length);
+                found
+            })
+        }
+        _ if expected == received => None,
+        _ => differ(),
+    }
+}
+
+/// Serves the answers of a `TraceRecord` trace, checking the client sends the recorded requests, see `HLAPIBus::replay`
+pub struct ReplayTransport {
+    records: Vec<TraceRecord>,
+    config: ReplayConfig,
+    cursor: usize, // next record
+    packets: usize, // client packets so far
+    outgoing: VecDeque<u8>, // answers due, delimited
+    incoming: Splitter,
+    divergence: Option<Divergence>,
+}
+
+impl ReplayTransport {
+    pub fn new(records: Vec<TraceRecord>, config: ReplayConfig) -> Self {
+        let mut transport = Self { records, config, cursor: 0, packets: 0, outgoing: VecDeque::new(), incoming: Splitter::default(), divergence: None };
+        transport.queue_answers(); // unsolicited packets at the start
+        transport
+    }
+
+    pub fn open(path: impl AsRef<Path>, config: ReplayConfig) -> IOResult<Self> {
+        Ok(Self::new(TraceRecord::read_all(path)?, config))
+    }
+
+    pub fn divergence(&self) -> Option<&Divergence> { self.divergence.as_ref() }
+
+    /// Recorded packets not reached yet
+    pub fn remaining(&self) -> &[TraceRecord] { &self.records[self.cursor..] }
+
+    /// Fails on the first divergence, or if the client stopped before the end of the trace
+    pub fn finish(&self) -> Result<(), Divergence> {
+        if let Some(divergence) = &self.divergence { return Err(divergence.clone()); }
+        match self.remaining().iter().position(|record| record.direction == TraceDirection::Out) {
+            None => Ok(()),
+            Some(offset) => Err(Divergence {
+                packet: self.packets, record: self.cursor + offset, path: String::new(),
+                expected: Some(self.remaining()[offset].frame.clone()), received: "nothing".to_owned(),
+            }),
+        }
+    }
+
+    /// Moves the answers up to the next client packet to the read side
+    fn queue_answers(&mut self) {
+        while let Some(record) = self.records.get(self.cursor).filter(|record| record.direction == TraceDirection::In) {
+            self.outgoing.extend(DELIM);
+            self.outgoing.extend(record.frame.as_bytes());
+            self.outgoing.extend(DELIM);
+            self.cursor += 1;
+        }
+    }
+
+    fn check(&mut self, packet: &[u8]) -> Option<Divergence> {
+        let (index, received) = (self.packets, String::from_utf8_lossy(packet).into_owned());
+        self.packets += 1;
+        let Some(record) = self.records.get(self.cursor) else {
+            return Some(Divergence { packet: index, record: self.cursor, path: String::new(), expected: None, received });
+        };
+        let divergence = |path: String, expected: String, received: String| Divergence { packet: index, record: self.cursor, path, expected: Some(expected), received };
+
+        let found = if record.truncated { None } // nothing to compare the tail against
+        else {
+            match (serde_json::from_str::<Value>(&record.frame), serde_json::from_slice::<Value>(packet)) {
+                (Ok(expected), Ok(received)) => compare(&self.config, &mut String::new(), &expected, &received)
+                    .map(|(path, expected, received)| divergence(path, expected.to_string(), received.to_string())),
+                _ if record.frame.as_bytes() == packet => None,
+                _ => Some(divergence(String::new(), record.frame.clone(), received)),
+            }
+        };
+        if found.is_none() {
+            self.cursor += 1;
+            self.queue_answers();
+        }
+        found
+    }
+
+    fn diverged(divergence: &Divergence) -> IOError {
+        IOError::new(IOErrorKind::InvalidData, divergence.clone())
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let count = buf.len().min(self.outgoing.len());
+        for (slot, byte) in buf.iter_mut().zip(self.outgoing.drain(..count)) { *slot = byte; }
+        Ok(count)
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        if let Some(divergence) = &self.divergence { Err(Self::diverged(divergence))? }
+
+        let mut packets = Vec::new();
+        self.incoming.feed(buf, |packet| packets.push(packet.to_vec()));
+        for packet in packets {
+            if let Some(divergence) = self.check(&packet) {
+                let error = Self::diverged(&divergence);
+                self.divergence = Some(divergence);
+                Err(error)?
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> { Ok(()) }
+}
+
+impl Transport for ReplayTransport {
+    /// Same as the mock, nothing can come that wasn't queued already
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> {
+        match (self.outgoing.is_empty(), timeout) {
+            (false, _) => Ok(true),
+            (true, Some(timeout)) => { std::thread::sleep(timeout); Ok(false) }
+            (true, None) => Err(IOError::new(IOErrorKind::WouldBlock, "the replayed trace has nothing left to answer")),
+        }
+    }
+}
+
+pub type ReplayBus = HLAPIBus<ReplayTransport>;
+
+impl HLAPIBus<ReplayTransport> {
+    /// Bus answering from a trace recorded with `HLAPIBus::traced`
+    pub fn replay(path: impl AsRef<Path>, config: ReplayConfig) -> IOResult<Self> {
+        Ok(Self::new(ReplayTransport::open(path, config)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::mock::{MockDevice, MockTransport};
+    use crate::trace::{TraceConfig, TraceWriter};
+    use crate::error::HLAPIError;
+    use crate::types::{HLAPIDeviceHandle, EMPTY};
+    use super::*;
+
+    const RECORDED: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+    const OTHER: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(2);
+
+    fn record(direction: TraceDirection, frame: Value) -> TraceRecord {
+        let frame = frame.to_string();
+        TraceRecord { timestamp_ms: 0, direction, size: frame.len(), latency_ms: None, device: None, method: None, frame, truncated: false }
+    }
+
+    /// One recorded `move(parameters)` call on `RECORDED`, answered with `true`
+    fn replaying(parameters: Value, config: ReplayConfig) -> ReplayBus {
+        HLAPIBus::new(ReplayTransport::new(vec![
+            record(TraceDirection::Out, json!({"type": "invoke", "data": {"deviceId": RECORDED, "name": "move", "parameters": parameters}})),
+            record(TraceDirection::In, json!({"type": "result", "data": true})),
+        ], config))
+    }
+
+    fn divergence<V>(bus: &ReplayBus, result: Result<V, HLAPIError>) -> Divergence {
+        assert!(matches!(result, Err(HLAPIError::Io(ref error)) if error.kind() == IOErrorKind::InvalidData));
+        bus.transport().divergence().cloned().unwrap()
+    }
+
+    #[test]
+    fn replays_a_recorded_trace() {
+        let path = std::env::temp_dir().join(format!("oc2devices-{}-replay.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let device = MockDevice::new(RECORDED, ["robot"]).method("move", &["java.lang.String", "double"], "boolean", |_| Ok(json!(true)));
+        let mut bus = HLAPIBus::new(MockTransport::new().with_device(device)).traced(TraceWriter::create(TraceConfig::new(&path)).unwrap());
+        assert_eq!(bus.list().unwrap().len(), 1);
+        assert!(bus.raw_call::<_, _, bool>(RECORDED, "move", ("forward", 1.5)).unwrap());
+        drop(bus);
+
+        let mut bus = ReplayBus::replay(&path, ReplayConfig::strict()).unwrap();
+        assert_eq!(bus.list().unwrap()[0].device_id, RECORDED);
+        assert_eq!(bus.transport().finish().unwrap_err().record, 2); // the call is still due
+        assert!(bus.raw_call::<_, _, bool>(RECORDED, "move", ("forward", 1.5)).unwrap());
+        assert!(bus.transport().finish().is_ok() && bus.transport().remaining().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_where_requests_diverge() {
+        let mut bus = replaying(json!(["forward", 1]), ReplayConfig::strict());
+        let result = bus.raw_call::<_, _, bool>(RECORDED, "move", ("back", 1));
+        let divergence = divergence(&bus, result);
+        assert_eq!((divergence.packet, divergence.record, divergence.path.as_str()), (0, 0, "data.parameters[0]"));
+        assert_eq!((divergence.expected.as_deref(), divergence.received.as_str()), (Some("\"forward\""), "\"back\""));
+
+        // Stays failed
+        assert!(bus.raw_call::<_, _, bool>(RECORDED, "move", ("forward", 1)).is_err());
+        assert_eq!(bus.transport().finish().unwrap_err(), divergence);
+    }
+
+    #[test]
+    fn reports_requests_past_the_end() {
+        let mut bus = replaying(json!([]), ReplayConfig::strict());
+        assert!(bus.raw_call::<_, _, bool>(RECORDED, "move", EMPTY).unwrap());
+        let result = bus.raw_call::<_, _, bool>(RECORDED, "move", EMPTY);
+        let divergence = divergence(&bus, result);
+        assert_eq!((divergence.packet, divergence.record, divergence.expected), (1, 2, None));
+    }
+
+    #[test]
+    fn ignores_uuids_when_asked() {
+        let mut bus = replaying(json!([]), ReplayConfig::strict());
+        let result = bus.raw_call::<_, _, bool>(OTHER, "move", EMPTY);
+        assert_eq!(divergence(&bus, result).path, "data.deviceId");
+
+        let mut bus = replaying(json!([]), ReplayConfig { ignore_uuids: true, ..ReplayConfig::strict() });
+        assert!(bus.raw_call::<_, _, bool>(OTHER, "move", EMPTY).unwrap());
+    }
+
+    #[test]
+    fn tolerates_float_differences_when_asked() {
+        let mut bus = replaying(json!([1.0]), ReplayConfig::strict());
+        let result = bus.raw_call::<_, _, bool>(RECORDED, "move", (1.0000001,));
+        assert_eq!(divergence(&bus, result).path, "data.parameters[0]");
+
+        let mut bus = replaying(json!([1.0]), ReplayConfig::lenient());
+        assert!(bus.raw_call::<_, _, bool>(RECORDED, "move", (1.0000001,)).unwrap());
+
+        let mut bus = replaying(json!([1]), ReplayConfig { float_tolerance: Some(10.0), ..ReplayConfig::strict() });
+        let result = bus.raw_call::<_, _, bool>(RECORDED, "move", (2,)); // integers are compared exactly
+        assert_eq!(divergence(&bus, result).path, "data.parameters[0]");
+    }
+
+    #[test]
+    fn ignores_paths_when_asked() {
+        let config = ReplayConfig::strict().ignore_path("data.parameters[1]");
+        let mut bus = replaying(json!(["forward", 1]), config.clone());
+        assert!(bus.raw_call::<_, _, bool>(RECORDED, "move", ("forward", 2)).unwrap());
+
+        let mut bus = replaying(json!(["forward", 1]), config);
+        let result = bus.raw_call::<_, _, bool>(RECORDED, "move", ("back", 2));
+        assert_eq!(divergence(&bus, result).path, "data.parameters[0]");
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::io::{Result as IOResult, Error as IOError, ErrorKind as IOErrorKind};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::types::*;
+use crate::error::*;
+use crate::signature;
+use crate::transport::Transport;
+use crate::codegen::DeviceDump;
+use crate::HLAPIBus;
+
+/// Bumped on any incompatible change of the file format
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What every component of a world looks like, see `Schema::capture`
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schema {
+    pub version: u32,
+    pub captured_at: String, // RFC 3339, UTC
+    pub components: BTreeMap<String, ComponentSchema>, // by type name
+}
+
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentSchema {
+    pub devices: usize, // having the component when captured
+    pub methods: Vec<HLAPIMethod>,
+}
+
+impl Schema {
+    /// Lists every device then their methods
+    pub fn capture<T: Transport>(bus: &mut HLAPIBus<T>) -> HLAPIResult<Self> {
+        Ok(Self::from_devices(&DeviceDump::capture(bus)?))
+    }
+
+    /// Devices having the same component only appear once, with the methods they all share
+    pub fn from_devices(devices: &[DeviceDump]) -> Self {
+        let mut components: BTreeMap<String, ComponentSchema> = BTreeMap::new();
+        for device in devices {
+            for component in &device.descriptor.components {
+                match components.get_mut(component) {
+                    None => { components.insert(component.clone(), ComponentSchema { devices: 1, methods: device.methods.clone() }); }
+                    Some(known) => {
+                        known.devices += 1;
+                        known.methods.retain(|method| device.methods.iter().any(|other| signature::signature(other) == signature::signature(method)));
+                    }
+                }
+            }
+        }
+        Self { version: SCHEMA_VERSION, captured_at: now_rfc3339(), components }
+    }
+
+    /// Fails on schemas from a newer version
+    pub fn read(path: impl AsRef<Path>) -> IOResult<Self> {
+        let schema: Self = serde_json::from_slice(&std::fs::read(path)?)?;
+        if schema.version > SCHEMA_VERSION {
+            Err(IOError::new(IOErrorKind::InvalidData, format!("schema version {} is newer than the supported {SCHEMA_VERSION}", schema.version)))?
+        }
+        Ok(schema)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> IOResult<()> {
+        let mut json = serde_json::to_vec_pretty(self)?;
+        json.push(b'\n');
+        std::fs::write(path, json)
+    }
+}
+
+/// `2022-03-05T14:07:31Z`, there is no date handling in std
+fn now_rfc3339() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()) as i64;
+    let (days, time) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z", time / 3600, time / 60 % 60, time % 60)
+}
+
+/// One difference between two schemas, see `Schema::diff`
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "change")]
+pub enum SchemaChange {
+    ComponentAdded { component: String },
+    ComponentRemoved { component: String },
+    /// `method` being the full signature, as overloads share their name
+    MethodAdded { component: String, method: String },
+    MethodRemoved { component: String, method: String },
+    /// Same name, but no overload left with the old parameters
+    ParametersChanged { component: String, method: String, before: Vec<String>, after: Vec<String> },
+    ReturnTypeChanged { component: String, method: String, before: String, after: String },
+}
+
+impl SchemaChange {
+    /// Whether code written against the old schema may stop working, only additions are safe
+    pub fn is_breaking(&self) -> bool { !matches!(self, Self::ComponentAdded { .. } | Self::MethodAdded { .. }) }
+}
+
+impl std::fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ComponentAdded { component } => write!(f, "+ {component}"),
+            Self::ComponentRemoved { component } => write!(f, "- {component}"),
+            Self::MethodAdded { component, method } => write!(f, "+ {component}.{method}"),
+            Self::MethodRemoved { component, method } => write!(f, "- {component}.{method}"),
+            Self::ParametersChanged { component, method, before, after } =>
+                write!(f, "~ {component}.{method}: parameters ({}) became ({})", before.join(", "), after.join(", ")),
+            Self::ReturnTypeChanged { component, method, before, after } => write!(f, "~ {component}.{method}: returns {after} instead of {before}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+#[derive(Serialize, Deserialize)]
+pub struct SchemaDiff {
+    pub breaking: bool,
+    pub changes: Vec<SchemaChange>,
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+    pub fn breaking_changes(&self) -> impl Iterator<Item = &SchemaChange> { self.changes.iter().filter(|change| change.is_breaking()) }
+}
+
+/// Parameter types, normalized so that `[B` and `byte[]` compare equal
+fn parameter_types(method: &HLAPIMethod) -> Vec<String> { method.parameter_types().map(|java| java.to_string()).collect() }
+
+fn overloads<'m>(methods: &'m [HLAPIMethod], name: &str) -> Vec<&'m HLAPIMethod> {
+    methods.iter().filter(|method| method.name == name).collect()
+}
+
+/// Changes between the overloads of a method, matched by parameters when there are several
+fn diff_method(component: &str, before: &[&HLAPIMethod], after: &[&HLAPIMethod], changes: &mut Vec<SchemaChange>) {
+    let (method, component) = (before.iter().chain(after).next().map_or("", |method| method.name.as_str()), component.to_owned());
+    let returns = |before: &HLAPIMethod, after: &HLAPIMethod, changes: &mut Vec<SchemaChange>| {
+        let (old, new) = (before.return_java_type().to_string(), after.return_java_type().to_string());
+        if old != new { changes.push(SchemaChange::ReturnTypeChanged { component: component.clone(), method: method.to_owned(), before: old, after: new }); }
+    };
+
+    if let ([old], [new]) = (before, after) {
+        let (old_parameters, new_parameters) = (parameter_types(old), parameter_types(new));
+        if old_parameters != new_parameters {
+            changes.push(SchemaChange::ParametersChanged { component: component.clone(), method: method.to_owned(), before: old_parameters, after: new_parameters });
+        }
+        returns(old, new, changes);
+        return;
+    }
+
+    for old in before {
+        match after.iter().find(|new| parameter_types(new) == parameter_types(old)) {
+            Some(new) => returns(old, new, changes),
+            None => changes.push(SchemaChange::MethodRemoved { component: component.clone(), method: signature::signature(old) }),
+        }
+    }
+    for new in after {
+        if !before.iter().any(|old| parameter_types(old) == parameter_types(new)) {
+            changes.push(SchemaChange::MethodAdded { component: component.clone(), method: signature::signature(new) });
+        }
+    }
+}
+
+impl Schema {
+    /// What changed from `self` to `newer`, components and methods in alphabetical order
+    pub fn diff(&self, newer: &Schema) -> SchemaDiff {
+        let mut changes = Vec::new();
+
+        for (component, old) in &self.components {
+            let Some(new) = newer.components.get(component) else {
+                changes.push(SchemaChange::ComponentRemoved { component: component.clone() });
+                continue;
+            };
+            let mut names: Vec<&str> = old.methods.iter().chain(&new.methods).map(|method| method.name.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+
+            for name in names {
+                match (overloads(&old.methods, name), overloads(&new.methods, name)) {
+                    (before, after) if after.is_empty() => changes.extend(before.iter().map(|method| SchemaChange::MethodRemoved { component: component.clone(), method: signature::signature(method) })),
+                    (before, after) if before.is_empty() => changes.extend(after.iter().map(|method| SchemaChange::MethodAdded { component: component.clone(), method: signature::signature(method) })),
+                    (before, after) => diff_method(component, &before, &after, &mut changes),
+                }
+            }
+        }
+        for component in newer.components.keys() {
+            if !self.components.contains_key(component) { changes.push(SchemaChange::ComponentAdded { component: component.clone() }); }
+        }
+
+        SchemaDiff { breaking: changes.iter().any(SchemaChange::is_breaking), changes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn method(name: &str, parameters: &[&str], return_type: &str) -> HLAPIMethod {
+        HLAPIMethod {
+            name: name.to_owned(),
+            parameters: parameters.iter().map(|&ty| HLAPIType::new(ty)).collect(),
+            return_type: return_type.to_owned(),
+            description: None,
+            return_value_description: None,
+        }
+    }
+
+    fn schema(components: &[(&str, Vec<HLAPIMethod>)]) -> Schema {
+        Schema {
+            version: SCHEMA_VERSION,
+            captured_at: now_rfc3339(),
+            components: components.iter().map(|(name, methods)| (name.to_string(), ComponentSchema { devices: 1, methods: methods.clone() })).collect(),
+        }
+    }
+
+    #[test]
+    fn identical_schemas_have_no_changes() {
+        let old = schema(&[("redstone", vec![method("getRedstoneInput", &["java.lang.String"], "int")])]);
+        let diff = old.diff(&old.clone());
+        assert!(diff.is_empty() && !diff.breaking);
+    }
+
+    #[test]
+    fn added_components_and_methods_are_not_breaking() {
+        let old = schema(&[("redstone", vec![method("getRedstoneInput", &["java.lang.String"], "int")])]);
+        let new = schema(&[
+            ("redstone", vec![method("getRedstoneInput", &["java.lang.String"], "int"), method("getRedstoneOutput", &["java.lang.String"], "int")]),
+            ("sound", vec![]),
+        ]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.changes, [
+            SchemaChange::MethodAdded { component: "redstone".into(), method: signature::signature(&new.components["redstone"].methods[1]) },
+            SchemaChange::ComponentAdded { component: "sound".into() },
+        ]);
+        assert!(!diff.breaking);
+    }
+
+    #[test]
+    fn removed_components_and_methods_are_breaking() {
+        let old = schema(&[("redstone", vec![method("getRedstoneInput", &["java.lang.String"], "int")]), ("sound", vec![])]);
+        let new = schema(&[("redstone", vec![])]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.changes, [
+            SchemaChange::MethodRemoved { component: "redstone".into(), method: signature::signature(&old.components["redstone"].methods[0]) },
+            SchemaChange::ComponentRemoved { component: "sound".into() },
+        ]);
+        assert!(diff.breaking && diff.breaking_changes().count() == 2);
+    }
+
+    #[test]
+    fn changed_signatures_are_breaking() {
+        let old = schema(&[("redstone", vec![method("setRedstoneOutput", &["java.lang.String", "int"], "void")])]);
+        let new = schema(&[("redstone", vec![method("setRedstoneOutput", &["java.lang.String"], "boolean")])]);
+        let diff = old.diff(&new);
+        assert!(matches!(&diff.changes[..], [
+            SchemaChange::ParametersChanged { method, before, after, .. },
+            SchemaChange::ReturnTypeChanged { .. },
+        ] if method == "setRedstoneOutput" && before.len() == 2 && after.len() == 1), "{:?}", diff.changes);
+        assert!(diff.breaking);
+    }
+
+    #[test]
+    fn overloads_are_matched_by_parameters() {
+        let old = schema(&[("file", vec![method("read", &["int"], "[B"), method("read", &["int", "int"], "[B")])]);
+        let new = schema(&[("file", vec![method("read", &["int"], "java.lang.String"), method("read", &["java.lang.String"], "[B")])]);
+        let diff = old.diff(&new);
+        assert!(matches!(&diff.changes[..], [
+            SchemaChange::ReturnTypeChanged { .. },
+            SchemaChange::MethodRemoved { method: removed, .. },
+            SchemaChange::MethodAdded { method: added, .. },
+        ] if *removed == signature::signature(&old.components["file"].methods[1]) && *added == signature::signature(&new.components["file"].methods[1])),
+            "{:?}", diff.changes);
+        assert!(diff.breaking && !diff.changes[2].is_breaking());
+    }
+
+    #[test]
+    fn only_added_overloads_are_not_breaking() {
+        let old = schema(&[("file", vec![method("read", &["int"], "[B")])]);
+        let new = schema(&[("file", vec![method("read", &["int"], "[B"), method("read", &["int", "int"], "[B")])]);
+        let diff = old.diff(&new);
+        assert!(matches!(&diff.changes[..], [SchemaChange::MethodAdded { .. }]) && !diff.breaking, "{:?}", diff.changes);
+    }
+
+    #[test]
+    fn shared_components_keep_common_methods() {
+        let device = |methods| DeviceDump {
+            descriptor: HLAPIDeviceDescriptor { device_id: HLAPIDeviceHandle::nil(), components: vec!["file".into()] },
+            methods,
+        };
+        let schema = Schema::from_devices(&[
+            device(vec![method("read", &["int"], "[B"), method("write", &["[B"], "void")]),
+            device(vec![method("read", &["int"], "[B")]),
+        ]);
+        assert_eq!(schema.components["file"].devices, 2);
+        assert_eq!(schema.components["file"].methods.len(), 1);
+    }
+}
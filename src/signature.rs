@@ -0,0 +1,263 @@
+use std::fmt::{Display, Formatter};
+use serde_json::Value;
+use crate::types::HLAPIMethod;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Primitive { Boolean, Byte, Short, Int, Long, Float, Double, Char }
+
+impl Primitive {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "boolean" => Self::Boolean, "byte" => Self::Byte, "short" => Self::Short, "int" => Self::Int,
+            "long" => Self::Long, "float" => Self::Float, "double" => Self::Double, "char" => Self::Char,
+            _ => None?
+        })
+    }
+
+    /// `java.lang` wrapper class
+    pub fn from_boxed_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "java.lang.Boolean" => Self::Boolean, "java.lang.Byte" => Self::Byte, "java.lang.Short" => Self::Short, "java.lang.Integer" => Self::Int,
+            "java.lang.Long" => Self::Long, "java.lang.Float" => Self::Float, "java.lang.Double" => Self::Double, "java.lang.Character" => Self::Char,
+            _ => None?
+        })
+    }
+
+    /// JVM descriptor letter, as in `[B`
+    pub fn from_descriptor(letter: char) -> Option<Self> {
+        Some(match letter {
+            'Z' => Self::Boolean, 'B' => Self::Byte, 'S' => Self::Short, 'I' => Self::Int,
+            'J' => Self::Long, 'F' => Self::Float, 'D' => Self::Double, 'C' => Self::Char,
+            _ => None?
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Boolean => "boolean", Self::Byte => "byte", Self::Short => "short", Self::Int => "int",
+            Self::Long => "long", Self::Float => "float", Self::Double => "double", Self::Char => "char",
+        }
+    }
+
+    pub fn boxed_name(self) -> &'static str {
+        match self {
+            Self::Boolean => "java.lang.Boolean", Self::Byte => "java.lang.Byte", Self::Short => "java.lang.Short", Self::Int => "java.lang.Integer",
+            Self::Long => "java.lang.Long", Self::Float => "java.lang.Float", Self::Double => "java.lang.Double", Self::Char => "java.lang.Character",
+        }
+    }
+
+    pub fn json_shape(self) -> JsonShape {
+        match self {
+            Self::Boolean => JsonShape::Bool,
+            Self::Byte | Self::Short | Self::Int | Self::Long => JsonShape::Integer,
+            Self::Float | Self::Double => JsonShape::Number,
+            Self::Char => JsonShape::String, // Gson writes chars as one character strings
+        }
+    }
+}
+
+/// Java type as named by the HLAPI method descriptors, e.g. `"int"`, `"java.lang.String"` or `"[B"`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum JavaType {
+    Void,
+    Primitive(Primitive),
+    /// Wrapper class, may be null
+    Boxed(Primitive),
+    String,
+    Array(Box<JavaType>),
+    /// Any `java.util.Map`, generic parameters are erased
+    Map,
+    /// Any `java.util` collection, generic parameters are erased
+    List,
+    /// Any other class, by its fully qualified name
+    Object(String),
+}
+
+impl JavaType {
+    /// Never fails, unknown names are `Object`s
+    pub fn parse(name: &str) -> Self {
+        let name = name.trim();
+        let name = name.split_once('<').map_or(name, |(raw, _)| raw); // generics, when sent
+        if let Some(element) = name.strip_suffix("[]") { // source form, `byte[]`
+            return Self::Array(Box::new(Self::parse(element)));
+        }
+        if let Some(element) = name.strip_prefix('[') { // JVM form, `[B`, `[Ljava.lang.String;`
+            return Self::Array(Box::new(Self::parse_descriptor(element)));
+        }
+
+        match name {
+            "void" | "java.lang.Void" => Self::Void,
+            "java.lang.String" | "java.lang.CharSequence" => Self::String,
+            "java.util.Map" | "java.util.HashMap" | "java.util.LinkedHashMap" | "java.util.TreeMap" => Self::Map,
+            "java.util.List" | "java.util.ArrayList" | "java.util.Collection" | "java.util.Set" | "java.util.HashSet" => Self::List,
+            _ => Primitive::from_name(name).map(Self::Primitive)
+                .or_else(|| Primitive::from_boxed_name(name).map(Self::Boxed))
+                .unwrap_or_else(|| Self::Object(name.to_owned())),
+        }
+    }
+
+    /// Element of a JVM array descriptor, past its first `[`
+    fn parse_descriptor(descriptor: &str) -> Self {
+        if let Some(element) = descriptor.strip_prefix('[') {
+            return Self::Array(Box::new(Self::parse_descriptor(element)));
+        }
+        if let Some(class) = descriptor.strip_prefix('L') {
+            return Self::parse(class.strip_suffix(';').unwrap_or(class));
+        }
+        let mut letters = descriptor.chars();
+        match (letters.next().and_then(Primitive::from_descriptor), letters.next()) {
+            (Some(primitive), None) => Self::Primitive(primitive),
+            _ => Self::Object(descriptor.to_owned()),
+        }
+    }
+
+    pub fn is_void(&self) -> bool { matches!(self, Self::Void) }
+
+    /// Whether `null` is a valid value, primitives aside everything is a reference
+    pub fn is_nullable(&self) -> bool { !matches!(self, Self::Primitive(_)) }
+
+    /// What the value looks like once encoded by Gson
+    pub fn json_shape(&self) -> JsonShape {
+        match self {
+            Self::Void => JsonShape::Null,
+            Self::Primitive(primitive) | Self::Boxed(primitive) => primitive.json_shape(),
+            Self::String => JsonShape::String,
+            Self::Array(element) => JsonShape::Array(Box::new(element.json_shape())),
+            Self::List => JsonShape::Array(Box::new(JsonShape::Any)),
+            Self::Map => JsonShape::Object,
+            Self::Object(_) => JsonShape::Any, // enums are strings, records are objects...
+        }
+    }
+
+    /// Whether `value` would be accepted by the Java side for this type
+    pub fn accepts(&self, value: &Value) -> bool {
+        (value.is_null() && self.is_nullable()) || self.json_shape().matches(value)
+    }
+}
+
+impl Display for JavaType {
+    /// Source form, `byte[]` rather than `[B`
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Void => f.write_str("void"),
+            Self::Primitive(primitive) => f.write_str(primitive.name()),
+            Self::Boxed(primitive) => f.write_str(primitive.boxed_name()),
+            Self::String => f.write_str("java.lang.String"),
+            Self::Array(element) => write!(f, "{element}[]"),
+            Self::Map => f.write_str("java.util.Map"),
+            Self::List => f.write_str("java.util.List"),
+            Self::Object(class) => f.write_str(class),
+        }
+    }
+}
+
+impl From<&str> for JavaType {
+    fn from(name: &str) -> Self { Self::parse(name) }
+}
+
+/// JSON value kinds, see `JavaType::json_shape`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum JsonShape {
+    Null,
+    Bool,
+    Integer,
+    /// Integers are numbers too
+    Number,
+    String,
+    Array(Box<JsonShape>),
+    Object,
+    Any,
+}
+
+impl JsonShape {
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::Any, _) | (Self::Null, Value::Null) | (Self::Bool, Value::Bool(_)) | (Self::String, Value::String(_)) | (Self::Object, Value::Object(_)) => true,
+            (Self::Integer, Value::Number(number)) => number.is_i64() || number.is_u64(),
+            (Self::Number, Value::Number(_)) => true,
+            (Self::Array(element), Value::Array(items)) => items.iter().all(|item| element.matches(item)),
+            _ => false,
+        }
+    }
+}
+
+impl Display for JsonShape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool => f.write_str("boolean"),
+            Self::Integer => f.write_str("integer"),
+            Self::Number => f.write_str("number"),
+            Self::String => f.write_str("string"),
+            Self::Array(element) => write!(f, "array of {element}"),
+            Self::Object => f.write_str("object"),
+            Self::Any => f.write_str("any value"),
+        }
+    }
+}
+
+/// Arguments as the Java side sees them: an array, or nothing for `null` and `{}` (how `Empty` used to be sent)
+pub fn positional(parameters: &Value) -> &[Value] {
+    match parameters {
+        Value::Array(args) => args,
+        Value::Object(map) if map.is_empty() => &[],
+        Value::Null => &[],
+        other => std::slice::from_ref(other),
+    }
+}
+
+/// `name(int, java.lang.String): boolean`
+pub fn signature(method: &HLAPIMethod) -> String {
+    let parameters: Vec<String> = method.parameters.iter().map(|parameter| match &parameter.name {
+        Some(name) => format!("{} {name}", parameter.java_type()),
+        None => parameter.java_type().to_string(),
+    }).collect();
+    format!("{}({}): {}", method.name, parameters.join(", "), method.return_java_type())
+}
+
+/// Why `args` don't fit `method`, `None` if they do
+pub fn mismatch(method: &HLAPIMethod, args: &[Value]) -> Option<String> {
+    if method.parameters.len() != args.len() {
+        return Some(format!("expected {} arguments, got {}", method.parameters.len(), args.len()));
+    }
+    method.parameters.iter().zip(args).enumerate().find_map(|(index, (parameter, arg))| {
+        let java_type = parameter.java_type();
+        (!java_type.accepts(arg)).then(|| format!("argument {} should be a {} ({java_type}), got {arg}", index + 1, java_type.json_shape()))
+    })
+}
+
+/// Arguments not fitting any overload of a method
+#[derive(Clone, Debug)]
+pub struct SignatureError {
+    pub method: String,
+    pub signatures: Vec<String>, // of every overload, empty if the method doesn't exist
+    pub reason: String,
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid call to {}: {}", self.method, self.reason)?;
+        if !self.signatures.is_empty() { write!(f, ", expected {}", self.signatures.join(" or "))?; }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SignatureError { }
+
+/// Overload of `name` accepting `args`
+pub fn resolve<'m>(methods: &'m [HLAPIMethod], name: &str, args: &[Value]) -> Result<&'m HLAPIMethod, SignatureError> {
+    let overloads: Vec<&HLAPIMethod> = methods.iter().filter(|method| method.name == name).collect();
+
+    match overloads.iter().copied().find(|method| mismatch(method, args).is_none()) {
+        Some(method) => Ok(method),
+        None => Err(SignatureError {
+            method: name.to_owned(),
+            signatures: overloads.iter().map(|method| signature(method)).collect(),
+            reason: match overloads.as_slice() {
+                [] => "no such method".to_owned(),
+                [single] => mismatch(single, args).unwrap_or_default(),
+                _ => "no overload matches the arguments".to_owned(),
+            },
+        }),
+    }
+}
@@ -0,0 +1,405 @@
+use std::io::BufRead;
+use std::marker::PhantomData;
+use serde::de::{DeserializeOwned, Error as _};
+use crate::types::*;
+use crate::error::*;
+use crate::transport::Transport;
+use crate::deadline::DeadlineReader;
+use crate::framer::{self, FrameReader};
+use crate::HLAPIBus;
+
+type ScanResult<T> = Result<T, serde_json::Error>;
+
+fn syntax(expected: &str) -> serde_json::Error { serde_json::Error::custom(format!("expected {expected}")) }
+fn missing(field: &str) -> serde_json::Error { serde_json::Error::custom(format!("missing field `{field}`")) }
+fn out_of_range(index: usize) -> serde_json::Error { serde_json::Error::custom(format!("no element at index {index}")) }
+
+fn peek<R: BufRead + ?Sized>(reader: &mut R) -> ScanResult<Option<u8>> {
+    Ok(reader.fill_buf().map_err(serde_json::Error::io)?.first().copied())
+}
+
+/// Next non whitespace byte, left unconsumed
+fn peek_token<R: BufRead + ?Sized>(reader: &mut R) -> ScanResult<Option<u8>> {
+    while let Some(byte) = peek(reader)? {
+        if !byte.is_ascii_whitespace() { return Ok(Some(byte)); }
+        reader.consume(1);
+    }
+    Ok(None)
+}
+
+fn expect<R: BufRead + ?Sized>(reader: &mut R, token: u8) -> ScanResult<()> {
+    if peek_token(reader)? != Some(token) { Err(syntax(&format!("'{}'", token as char)))? }
+    reader.consume(1);
+    Ok(())
+}
+
+/// Consumes a whole JSON value, copying its bytes to `out` if any, nothing gets decoded
+fn capture<R: BufRead + ?Sized>(reader: &mut R, mut out: Option<&mut Vec<u8>>) -> ScanResult<()> {
+    let mut push = |byte: u8| if let Some(out) = out.as_mut() { out.push(byte) };
+    let (mut depth, mut string, mut escaped) = (0usize, false, false);
+
+    let first = peek_token(reader)?.ok_or_else(|| syntax("a value"))?;
+    if !matches!(first, b'{' | b'[' | b'"') { // scalar, ends on whatever can't be part of it
+        let mut length = 0;
+        while let Some(byte) = peek(reader)? {
+            if byte.is_ascii_whitespace() || matches!(byte, b',' | b']' | b'}' | b':') { break; }
+            push(byte);
+            reader.consume(1);
+            length += 1;
+        }
+        if length == 0 { Err(syntax("a value"))? }
+        return Ok(());
+    }
+    loop {
+        let byte = peek(reader)?.ok_or_else(|| syntax("the end of the value"))?;
+        reader.consume(1);
+        push(byte);
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' if string => escaped = true,
+            b'"' => string = !string,
+            _ if string => { }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            _ => { }
+        }
+        if depth == 0 && !string { return Ok(()); }
+    }
+}
+
+fn key<R: BufRead + ?Sized>(reader: &mut R) -> ScanResult<String> {
+    if peek_token(reader)? != Some(b'"') { Err(syntax("a key"))? }
+    let mut bytes = Vec::new();
+    capture(reader, Some(&mut bytes))?;
+    expect(reader, b':')?;
+    serde_json::from_slice(&bytes)
+}
+
+/// `false` on a `}`, past a `,` otherwise
+fn more_entries<R: BufRead + ?Sized>(reader: &mut R, close: u8) -> ScanResult<bool> {
+    match peek_token(reader)? {
+        Some(b',') => { reader.consume(1); Ok(true) }
+        Some(byte) if byte == close => { reader.consume(1); Ok(false) }
+        _ => Err(syntax(&format!("',' or '{}'", close as char))),
+    }
+}
+
+/// Walks down `path` (object keys, or indexes within arrays) and past the `[` of the array there, `false` if it's `null`
+fn descend<R: BufRead + ?Sized>(reader: &mut R, path: &[&str]) -> ScanResult<bool> {
+    for segment in path {
+        match peek_token(reader)? {
+            Some(b'{') => {
+                reader.consume(1);
+                if peek_token(reader)? == Some(b'}') { Err(missing(segment))? }
+                loop {
+                    if key(reader)? == *segment { break; }
+                    capture(reader, None)?;
+                    if !more_entries(reader, b'}')? { Err(missing(segment))? }
+                }
+            }
+            Some(b'[') => {
+                let index: usize = segment.parse().map_err(|_| syntax(&format!("an object with a {segment} key")))?;
+                reader.consume(1);
+                for _ in 0..index {
+                    if peek_token(reader)? == Some(b']') { Err(out_of_range(index))? }
+                    capture(reader, None)?;
+                    if !more_entries(reader, b']')? { Err(out_of_range(index))? }
+                }
+                if peek_token(reader)? == Some(b']') { Err(out_of_range(index))? }
+            }
+            Some(b'n') => { capture(reader, None)?; return Ok(false); }
+            _ => Err(syntax(&format!("an object or array holding {segment}")))?,
+        }
+    }
+    match peek_token(reader)? {
+        Some(b'[') => { reader.consume(1); Ok(true) }
+        Some(b'n') => { capture(reader, None)?; Ok(false) }
+        _ => Err(syntax("an array")),
+    }
+}
+
+fn kind_name(kind: &str) -> &'static str {
+    match kind {
+        "list" => "list",
+        "methods" => "methods",
+        "error" => "error",
+        "result" => "result",
+        _ => "unknown",
+    }
+}
+
+/// Consumes the rest of the current packet, on failure the bus drains before the next call
+fn finish_frame<T: Transport>(bus: &mut HLAPIBus<T>) -> HLAPIResult<()> {
+    let mut reader = DeadlineReader { transport: &mut bus.handle, deadline: bus.deadline, cancel: bus.cancel.as_ref() };
+    let mut buffer = bus.buffer.reader(&mut reader);
+    let result = FrameReader::new(&mut buffer).finish().map(drop).map_err(HLAPIError::from);
+    if result.is_err() { bus.stale = true; }
+    bus.recover(result)
+}
+
+enum State {
+    Items { first: bool },
+    Done,
+}
+
+/// Pulls the elements of an array result one by one, out of the packet as it comes in, see `HLAPIBus::raw_call_iter`
+/// Dropping it early still consumes the rest of the packet, so that the bus stays in sync
+pub struct ResultIter<'b, T: Transport, Item> {
+    bus: &'b mut HLAPIBus<T>,
+    context: String,
+    device: HLAPIDeviceHandle,
+    captured: Option<(Vec<u8>, usize)>, // the result came before its type, so got buffered
+    open: bool, // the closing delimiter is still to be read
+    state: State,
+    index: usize,
+    _item: PhantomData<fn() -> Item>,
+}
+
+impl<'b, T: Transport, Item: DeserializeOwned> ResultIter<'b, T, Item> {
+    /// Expects the request to be sent already
+    pub(crate) fn open(bus: &'b mut HLAPIBus<T>, device: HLAPIDeviceHandle, method: &str, path: &[&str]) -> HLAPIResult<Self> {
+        let mut iter = Self {
+            bus, device, context: format!("streamed result of {method}"),
+            captured: None, open: false, state: State::Done, index: 0, _item: PhantomData,
+        };
+        let result = iter.begin(path);
+        if result.is_err() { let _ = iter.close(); }
+        let result = result.and_then(|streaming| {
+            if streaming { iter.state = State::Items { first: true }; }
+            else { iter.close()?; }
+            Ok(())
+        });
+        let result = iter.bus.recover(result);
+        iter.bus.forget_unknown(device, result)?;
+        Ok(iter)
+    }
+
+    /// Reads up to the array, `false` if there is none
+    fn begin(&mut self, path: &[&str]) -> HLAPIResult<bool> {
+        let bus = &mut *self.bus;
+        let mut reader = DeadlineReader { transport: &mut bus.handle, deadline: bus.deadline, cancel: bus.cancel.as_ref() };
+        let mut buffer = bus.buffer.reader(&mut reader);
+        bus.skipped += framer::begin(&mut buffer)?;
+        self.open = true;
+
+        let mut frame = FrameReader::new(&mut buffer);
+        let (mut kind, mut data) = (None::<String>, None::<Vec<u8>>);
+        let streaming = (|| -> ScanResult<Option<bool>> {
+            expect(&mut frame, b'{')?;
+            if peek_token(&mut frame)? == Some(b'}') { frame.consume(1); return Ok(None); }
+            loop {
+                match key(&mut frame)?.as_str() {
+                    "type" => {
+                        let mut bytes = Vec::new();
+                        capture(&mut frame, Some(&mut bytes))?;
+                        kind = Some(serde_json::from_slice(&bytes)?);
+                    }
+                    "data" if kind.as_deref() == Some("result") => return descend(&mut frame, path).map(Some),
+                    "data" => {
+                        let mut bytes = Vec::new();
+                        capture(&mut frame, Some(&mut bytes))?;
+                        data = Some(bytes);
+                    }
+                    _ => capture(&mut frame, None)?,
+                }
+                if !more_entries(&mut frame, b'}')? { return Ok(None); }
+            }
+        })();
+        self.open = !frame.is_done();
+        let streaming = streaming.map_err(|error| HLAPIError::deserialize(&self.context, error))?;
+        if let Some(streaming) = streaming { return Ok(streaming); }
+
+        match (kind.as_deref(), data) {
+            (Some("result"), None) => Ok(false), // `Void`
+            (Some("result"), Some(bytes)) => {
+                let mut rest = bytes.as_slice();
+                let streaming = descend(&mut rest, path).map_err(|error| HLAPIError::deserialize(&self.context, error))?;
+                let position = bytes.len() - rest.len();
+                self.captured = Some((bytes, position));
+                Ok(streaming)
+            }
+            (Some("error"), data) => {
+                let message = data.map(|bytes| serde_json::from_slice::<Option<String>>(&bytes)).transpose()
+                    .map_err(|error| HLAPIError::deserialize(&self.context, error))?.flatten();
+                Err(HLAPIError::Remote(RemoteError::new(message)))
+            }
+            (Some(kind), _) => Err(HLAPIError::UnexpectedResponse { expected: "result", received: kind_name(kind) }),
+            (None, _) => Err(HLAPIError::deserialize(&self.context, missing("type"))),
+        }
+    }
+
+    /// Raw bytes of the next element, `None` past the end of the array
+    fn next_element(&mut self) -> HLAPIResult<Option<Vec<u8>>> {
+        let State::Items { first } = self.state else { return Ok(None) };
+        let step = |reader: &mut dyn BufRead| -> ScanResult<Option<Vec<u8>>> {
+            match peek_token(reader)? {
+                Some(b']') => { reader.consume(1); return Ok(None); }
+                Some(b',') if !first => reader.consume(1),
+                _ if first => { }
+                _ => Err(syntax("',' or ']'"))?,
+            }
+            let mut bytes = Vec::new();
+            capture(reader, Some(&mut bytes))?;
+            Ok(Some(bytes))
+        };
+
+        let element = match &mut self.captured {
+            Some((bytes, position)) => {
+                let mut rest = &bytes[*position..];
+                let element = step(&mut rest);
+                *position = bytes.len() - rest.len();
+                element
+            }
+            None if !self.open => step(&mut &[][..]), // the packet ended within the array, the next one isn't ours to read
+            None => {
+                let bus = &mut *self.bus;
+                let mut reader = DeadlineReader { transport: &mut bus.handle, deadline: bus.deadline, cancel: bus.cancel.as_ref() };
+                let mut buffer = bus.buffer.reader(&mut reader);
+                let mut frame = FrameReader::new(&mut buffer);
+                let element = step(&mut frame);
+                self.open = !frame.is_done();
+                element
+            }
+        };
+        let element = element.map_err(|error| HLAPIError::deserialize(format_args!("element {} of the {}", self.index, self.context), error))?;
+        self.state = if element.is_some() { State::Items { first: false } } else { State::Done };
+        Ok(element)
+    }
+
+    /// Consumes the rest of the packet, fails if it ends early
+    pub fn close(&mut self) -> HLAPIResult<()> {
+        self.state = State::Done;
+        self.captured = None;
+        if !self.open { return Ok(()); }
+        self.open = false; // even if it fails, the bus drains before the next call then
+        finish_frame(self.bus)
+    }
+
+    /// Elements returned so far
+    pub fn streamed(&self) -> usize { self.index }
+}
+
+impl<T: Transport, Item: DeserializeOwned> Iterator for ResultIter<'_, T, Item> {
+    type Item = HLAPIResult<Item>;
+
+    /// An element not fitting `Item` is an error, but doesn't end the iteration, anything else does
+    fn next(&mut self) -> Option<Self::Item> {
+        let element = match self.next_element() {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return self.close().err().map(Err),
+            Err(error) => {
+                let _ = self.close();
+                let result = self.bus.recover(Err(error));
+                return Some(self.bus.forget_unknown(self.device, result));
+            }
+        };
+        let item = serde_json::from_slice(&element)
+            .map_err(|error| HLAPIError::deserialize(format_args!("element {} of the {}", self.index, self.context), error));
+        self.index += 1;
+        Some(item)
+    }
+}
+
+impl<T: Transport, Item> Drop for ResultIter<'_, T, Item> {
+    fn drop(&mut self) {
+        if self.open { let _ = finish_frame(self.bus); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::Duration;
+    use serde_json::{json, Value};
+    use crate::transport::MemoryTransport;
+    use crate::error::HLAPIError;
+    use crate::types::{HLAPIDeviceHandle, EMPTY};
+    use crate::HLAPIBus;
+
+    const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    /// A bus whose answers are written up front, requests going unread
+    fn bus(packets: &[&str]) -> (HLAPIBus<MemoryTransport>, MemoryTransport) {
+        let (client, mut server) = MemoryTransport::pair();
+        for packet in packets { write!(server, "\0{packet}\0").unwrap(); }
+        let mut bus = HLAPIBus::new(client);
+        bus.set_timeout(Some(Duration::from_millis(200)));
+        (bus, server)
+    }
+
+    fn collect<Item: serde::de::DeserializeOwned>(bus: &mut HLAPIBus<MemoryTransport>, path: &[&str]) -> Vec<Item> {
+        bus.raw_call_iter_at(DEVICE, "get", EMPTY, path).unwrap().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn streams_array_results() {
+        let (mut bus, _server) = bus(&[r#"{"type":"result","data":[1, 2 ,3]}"#, r#"{"type":"result","data":[]}"#]);
+        assert_eq!(collect::<i32>(&mut bus, &[]), [1, 2, 3]);
+        assert!(collect::<i32>(&mut bus, &[]).is_empty());
+    }
+
+    #[test]
+    fn null_results_yield_nothing() {
+        let (mut bus, _server) = bus(&[r#"{"type":"result","data":null}"#, r#"{"type":"result"}"#]);
+        assert!(collect::<i32>(&mut bus, &[]).is_empty());
+        assert!(collect::<i32>(&mut bus, &[]).is_empty());
+    }
+
+    #[test]
+    fn streams_nested_arrays() {
+        let (mut bus, _server) = bus(&[
+            r#"{"type":"result","data":{"size":27,"inventory":{"name":"chest","slots":[{"n":1},{"n":2}]}}}"#,
+            r#"{"type":"result","data":[["a"],["b","c"]]}"#,
+        ]);
+        assert_eq!(collect::<Value>(&mut bus, &["inventory", "slots"]), [json!({"n": 1}), json!({"n": 2})]);
+        assert_eq!(collect::<String>(&mut bus, &["1"]), ["b", "c"]);
+    }
+
+    #[test]
+    fn handles_data_before_type() {
+        let (mut bus, _server) = bus(&[
+            r#"{"data":{"slots":[4,5]},"type":"result"}"#,
+            r#"{"data":"no such method","type":"error"}"#,
+        ]);
+        assert_eq!(collect::<i32>(&mut bus, &["slots"]), [4, 5]);
+        let error = bus.raw_call_iter::<_, _, i32>(DEVICE, "get", EMPTY).err().unwrap();
+        assert!(matches!(error, HLAPIError::Remote(_)), "{error:?}");
+    }
+
+    #[test]
+    fn early_drop_keeps_the_bus_in_sync() {
+        let (mut bus, _server) = bus(&[r#"{"type":"result","data":[1,2,3]}"#, r#"{"type":"result","data":5}"#]);
+        let mut items = bus.raw_call_iter::<_, _, i32>(DEVICE, "get", EMPTY).unwrap();
+        assert_eq!(items.next().unwrap().unwrap(), 1);
+        drop(items);
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY).unwrap(), 5);
+    }
+
+    #[test]
+    fn mismatched_elements_do_not_end_the_iteration() {
+        let (mut bus, _server) = bus(&[r#"{"type":"result","data":[1,"two",3]}"#]);
+        let items: Vec<_> = bus.raw_call_iter::<_, _, i32>(DEVICE, "get", EMPTY).unwrap().collect();
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items[1], Err(HLAPIError::Deserialize { .. })));
+        assert_eq!(*items[2].as_ref().unwrap(), 3);
+    }
+
+    #[test]
+    fn truncated_packets_fail_without_reading_the_next_one() {
+        let (mut bus, _server) = bus(&[r#"{"type":"result","data":[1,2"#, r#"{"type":"result","data":5}"#]);
+        let items: Vec<_> = bus.raw_call_iter::<_, _, i32>(DEVICE, "get", EMPTY).unwrap().collect();
+        assert_eq!(items.len(), 3);
+        assert_eq!(*items[1].as_ref().unwrap(), 2);
+        let error = items[2].as_ref().unwrap_err();
+        assert!(matches!(error, HLAPIError::Deserialize { .. }) && error.to_string().contains("expected ',' or ']'"), "{error:?}");
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "get", EMPTY).unwrap(), 5);
+    }
+
+    #[test]
+    fn streamed_counts_processed_elements() {
+        let (mut bus, _server) = bus(&[r#"{"type":"result","data":[1,2,3]}"#]);
+        let mut seen = Vec::new();
+        let count = bus.raw_call_streamed(DEVICE, "get", EMPTY, |item: i32| -> Result<(), HLAPIError> { seen.push(item); Ok(()) }).unwrap();
+        assert_eq!((count, seen), (3, vec![1, 2, 3]));
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::Result as IOResult;
+use std::os::unix::io::RawFd;
+use std::sync::{Mutex, Once, TryLockError};
+use termios::Termios;
+
+/// Settings applied to the bus tty
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtyConfig {
+    pub raw: bool,
+    pub echo: bool,
+    pub baud: Option<termios::speed_t>, // None keeps the current speed
+}
+
+impl Default for TtyConfig {
+    /// raw -echo 38400
+    fn default() -> Self {
+        Self { raw: true, echo: false, baud: Some(termios::B38400) } // TODO: try faster BAUD rates
+    }
+}
+
+/// Original settings of every tty currently guarded, for `restore_all`
+static SAVED: Mutex<Vec<(RawFd, Termios)>> = Mutex::new(Vec::new());
+static AT_EXIT: Once = Once::new();
+
+/// Applies a `TtyConfig` and puts the original settings back when dropped, unwinding included, when the process exits, or on `restore_all`
+pub struct TermiosGuard {
+    descriptor: RawFd,
+    original: Termios,
+}
+
+impl TermiosGuard {
+    /// Must be dropped before `descriptor` gets closed
+    pub fn apply(descriptor: RawFd, config: &TtyConfig) -> IOResult<Self> {
+        let original = Termios::from_fd(descriptor)?;
+        let mut termios = original;
+
+        if config.raw { termios::cfmakeraw(&mut termios); }
+        if config.echo { termios.c_lflag |= termios::ECHO; } else { termios.c_lflag &= !termios::ECHO; }
+        if let Some(baud) = config.baud { termios::cfsetspeed(&mut termios, baud)?; } // before tcsetattr, or it never applies
+        termios::tcsetattr(descriptor, termios::TCSANOW, &termios)?; // immediate flush
+
+        SAVED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push((descriptor, original));
+        // SAFETY: the handler is a plain function, registered once
+        AT_EXIT.call_once(|| unsafe { libc::atexit(restore_at_exit); });
+
+        Ok(Self { descriptor, original })
+    }
+
+    pub fn original(&self) -> &Termios { &self.original }
+
+    pub fn restore(&self) -> IOResult<()> {
+        termios::tcsetattr(self.descriptor, termios::TCSANOW, &self.original)
+    }
+}
+
+impl Drop for TermiosGuard {
+    fn drop(&mut self) {
+        let _ = self.restore();
+        let mut saved = SAVED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(index) = saved.iter().position(|(descriptor, _)| *descriptor == self.descriptor) {
+            saved.remove(index);
+        }
+    }
+}
+
+fn restore(saved: &[(RawFd, Termios)]) {
+    for (descriptor, original) in saved {
+        let _ = termios::tcsetattr(*descriptor, termios::TCSANOW, original);
+    }
+}
+
+/// Run by `exit`, so after returning from `main` or on `std::process::exit`, guards held elsewhere never being dropped then
+/// Skipped rather than deadlocking if another thread is holding the lock as the process exits
+extern "C" fn restore_at_exit() {
+    match SAVED.try_lock() {
+        Ok(saved) => restore(&saved),
+        Err(TryLockError::Poisoned(poisoned)) => restore(&poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => {}
+    }
+}
+
+/// Puts back the original settings of every guarded tty, already done when the process exits or the guards are dropped
+/// Not async-signal-safe as it takes a lock: on SIGINT or SIGTERM, catch the signal on a regular thread
+/// (`signal-hook`, `tokio::signal`...) and call it from there before exiting
+pub fn restore_all() {
+    restore(&SAVED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+}
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{Result as IOResult, Error as IOError, LineWriter, Write, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::types::*;
+use crate::transport::Transport;
+use crate::DELIM;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceDirection {
+    /// VM to Java
+    Out,
+    /// Java to VM
+    In,
+}
+
+/// One line of a trace file, one per packet
+#[derive(Clone, Debug)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceRecord {
+    pub timestamp_ms: u64, // since the Unix epoch
+    pub direction: TraceDirection,
+    pub size: usize, // of the packet content, delimiters excluded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>, // answers only, since their request got sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<HLAPIDeviceHandle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    pub frame: String, // packet content, lossy for invalid UTF-8
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool, // frame cut to `TraceConfig::max_frame`
+}
+
+impl TraceRecord {
+    /// Parses every line of a trace file
+    pub fn read_all(path: impl AsRef<Path>) -> IOResult<Vec<Self>> {
+        let text = std::fs::read_to_string(path)?;
+        text.lines().filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(IOError::from))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceConfig {
+    pub path: PathBuf,
+    pub max_bytes: Option<u64>, // per file, rotated past it
+    pub keep: usize, // rotated files kept as `path.1`, `path.2`...
+    pub max_frame: Option<usize>, // frame bytes kept per record
+}
+
+impl TraceConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), max_bytes: Some(16 << 20), keep: 3, max_frame: Some(64 << 10) }
+    }
+}
+
+/// Appends `TraceRecord`s to a JSONL file, rotating it past `max_bytes`
+pub struct TraceWriter {
+    config: TraceConfig,
+    file: LineWriter<File>,
+    written: u64,
+    error: Option<IOError>, // first failure, tracing stops there
+}
+
+impl TraceWriter {
+    pub fn create(config: TraceConfig) -> IOResult<Self> {
+        let file = File::options().create(true).append(true).open(&config.path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { file: LineWriter::new(file), config, written, error: None })
+    }
+
+    pub fn config(&self) -> &TraceConfig { &self.config }
+
+    /// Why tracing stopped, the bus itself keeps working
+    pub fn error(&self) -> Option<&IOError> { self.error.as_ref() }
+
+    pub fn record(&mut self, record: &TraceRecord) {
+        if self.error.is_some() { return; }
+        if let Err(error) = self.write(record) { self.error = Some(error); }
+    }
+
+    fn write(&mut self, record: &TraceRecord) -> IOResult<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        if self.config.max_bytes.is_some_and(|max| self.written > 0 && self.written + line.len() as u64 > max) { self.rotate()?; }
+        self.file.write_all(&line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> IOResult<()> {
+        self.file.flush()?;
+        let rotated = |index: usize| {
+            let mut path = self.config.path.clone().into_os_string();
+            path.push(format!(".{index}"));
+            PathBuf::from(path)
+        };
+        if self.config.keep > 0 {
+            for index in (1..self.config.keep).rev() {
+                if rotated(index).exists() { std::fs::rename(rotated(index), rotated(index + 1))?; }
+            }
+            std::fs::rename(&self.config.path, rotated(1))?;
+        }
+        let file = File::options().create(true).write(true).truncate(true).open(&self.config.path)?;
+        (self.file, self.written) = (LineWriter::new(file), 0);
+        Ok(())
+    }
+}
+
+/// Packets being assembled from a byte stream, in either direction
+#[derive(Default)]
+pub(crate) struct Splitter { pending: Vec<u8> }
+
+impl Splitter {
+    pub(crate) fn feed(&mut self, bytes: &[u8], mut packet: impl FnMut(&[u8])) {
+        for &byte in bytes {
+            if byte != DELIM[0] { self.pending.push(byte); continue; }
+            if !self.pending.is_empty() { packet(&self.pending); } // resets and opening delimiters aside
+            self.pending.clear();
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as u64
+}
+
+/// Transport wrapper teeing every packet to a `TraceWriter`, see `HLAPIBus::traced`
+pub struct TracingTransport<T: Transport> {
+    inner: T,
+    writer: TraceWriter,
+    outgoing: Splitter,
+    incoming: Splitter,
+    request: Option<(Instant, Option<HLAPIDeviceHandle>, Option<String>)>, // last one sent
+}
+
+impl<T: Transport> TracingTransport<T> {
+    pub fn new(inner: T, writer: TraceWriter) -> Self {
+        Self { inner, writer, outgoing: Splitter::default(), incoming: Splitter::default(), request: None }
+    }
+
+    pub fn inner(&self) -> &T { &self.inner }
+    pub fn inner_mut(&mut self) -> &mut T { &mut self.inner }
+    pub fn writer(&self) -> &TraceWriter { &self.writer }
+    pub fn into_parts(self) -> (T, TraceWriter) { (self.inner, self.writer) }
+
+    fn record(writer: &mut TraceWriter, direction: TraceDirection, packet: &[u8], latency: Option<Duration>, device: Option<HLAPIDeviceHandle>, method: Option<String>) {
+        let kept = writer.config.max_frame.map_or(packet.len(), |max| max.min(packet.len()));
+        writer.record(&TraceRecord {
+            timestamp_ms: now_ms(),
+            direction,
+            size: packet.len(),
+            latency_ms: latency.map(|latency| latency.as_secs_f64() * 1000.0),
+            device,
+            method,
+            frame: String::from_utf8_lossy(&packet[..kept]).into_owned(),
+            truncated: kept < packet.len(),
+        });
+    }
+}
+
+impl<T: Transport> Read for TracingTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let count = self.inner.read(buf)?;
+        let (writer, request) = (&mut self.writer, &mut self.request);
+        self.incoming.feed(&buf[..count], |packet| {
+            let (latency, device, method) = match request.take() {
+                Some((sent, device, method)) => (Some(sent.elapsed()), device, method),
+                None => (None, None, None), // unsolicited
+            };
+            Self::record(writer, TraceDirection::In, packet, latency, device, method);
+        });
+        Ok(count)
+    }
+}
+
+impl<T: Transport> Write for TracingTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        let count = self.inner.write(buf)?;
+        let (writer, request) = (&mut self.writer, &mut self.request);
+        self.outgoing.feed(&buf[..count], |packet| {
+            let (device, method) = match serde_json::from_slice::<HLAPISend<String, Value>>(packet) {
+                Ok(HLAPISend::Methods(device)) => (Some(device), None),
+                Ok(HLAPISend::Invoke { device_id, method_name, .. }) => (Some(device_id), Some(method_name)),
+                _ => (None, None),
+            };
+            Self::record(writer, TraceDirection::Out, packet, None, device, method.clone());
+            *request = Some((Instant::now(), device, method));
+        });
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> IOResult<()> { self.inner.flush() }
+}
+
+impl<T: Transport> Transport for TracingTransport<T> {
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> { self.inner.wait_readable(timeout) }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::mock::{MockDevice, MockTransport};
+    use crate::HLAPIBus;
+    use super::*;
+
+    const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+    /// Fresh path in the temporary directory, rotated files included
+    fn temporary(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("oc2devices-{}-{name}.jsonl", std::process::id()));
+        for index in 0..10 {
+            let mut rotated = path.clone().into_os_string();
+            if index > 0 { rotated.push(format!(".{index}")); }
+            let _ = std::fs::remove_file(rotated);
+        }
+        path
+    }
+
+    fn bus(config: TraceConfig) -> HLAPIBus<TracingTransport<MockTransport>> {
+        let device = MockDevice::new(DEVICE, ["redstone"]).method("getRedstoneInput", &["java.lang.String"], "int", |_| Ok(json!(15)));
+        HLAPIBus::new(MockTransport::new().with_device(device)).traced(TraceWriter::create(config).unwrap())
+    }
+
+    #[test]
+    fn splits_packets_across_writes() {
+        let (mut splitter, mut packets) = (Splitter::default(), Vec::new());
+        for bytes in [&b"\0[1"[..], b",2]\0\0", b"\0", b"{}\0"] {
+            splitter.feed(bytes, |packet| packets.push(packet.to_vec()));
+        }
+        assert_eq!(packets, [b"[1,2]".to_vec(), b"{}".to_vec()]);
+    }
+
+    #[test]
+    fn records_requests_and_answers() {
+        let path = temporary("records");
+        let mut bus = bus(TraceConfig::new(&path));
+        bus.list().unwrap();
+        assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "getRedstoneInput", ("up",)).unwrap(), 15);
+        assert!(bus.transport().writer().error().is_none());
+
+        let records = TraceRecord::read_all(&path).unwrap();
+        let directions: Vec<_> = records.iter().map(|record| record.direction).collect();
+        assert_eq!(directions, [TraceDirection::Out, TraceDirection::In, TraceDirection::Out, TraceDirection::In]);
+        assert!(records[0].latency_ms.is_none() && records[1].latency_ms.is_some() && records[1].method.is_none());
+        for record in &records[2..] {
+            assert_eq!((record.device, record.method.as_deref()), (Some(DEVICE), Some("getRedstoneInput")));
+        }
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&records[3].frame).unwrap(), json!({"type": "result", "data": 15}));
+        assert!(records.iter().all(|record| record.size == record.frame.len() && !record.truncated));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncates_long_frames() {
+        let path = temporary("truncates");
+        let mut bus = bus(TraceConfig { max_frame: Some(8), ..TraceConfig::new(&path) });
+        bus.list().unwrap();
+
+        let records = TraceRecord::read_all(&path).unwrap();
+        assert!(records.iter().all(|record| record.truncated && record.frame.len() == 8 && record.size > 8));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rotates_past_max_bytes() {
+        let path = temporary("rotates");
+        let rotated = |index: usize| PathBuf::from(format!("{}.{index}", path.display()));
+        let mut bus = bus(TraceConfig { max_bytes: Some(400), keep: 2, ..TraceConfig::new(&path) });
+        for _ in 0..20 { bus.raw_call::<_, _, i32>(DEVICE, "getRedstoneInput", ("up",)).unwrap(); }
+        assert!(bus.transport().writer().error().is_none());
+
+        assert!(path.exists() && rotated(1).exists() && rotated(2).exists() && !rotated(3).exists());
+        for file in [path.clone(), rotated(1), rotated(2)] {
+            assert!(std::fs::metadata(&file).unwrap().len() <= 400);
+            assert!(!TraceRecord::read_all(&file).unwrap().is_empty());
+        }
+        for file in [path.clone(), rotated(1), rotated(2)] { std::fs::remove_file(file).unwrap(); }
+    }
+}
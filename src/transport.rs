@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::io::IsTerminal;
+use std::path::Path;
+use std::io::{Result as IOResult, Write, Read};
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::Duration;
+use epoll_rs::{Epoll, Opts as PollOpts};
+use crate::terminal::{TermiosGuard, TtyConfig};
+
+/// Main bus path
+pub const MAIN_BUS: &str = "/dev/hvc0";
+
+/// Byte pipe the HLAPI packets travel trough, reads and writes are blocking
+pub trait Transport: Read + Write {
+    /// Blocks until there is something to read, or until the timeout expires (`Ok(false)`), `None` waits forever
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool>;
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> { (**self).wait_readable(timeout) }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> { (**self).wait_readable(timeout) }
+}
+
+/// Any pair of file descriptors (pipes, ptys, sockets...), polled trough epoll
+pub struct FdTransport {
+    reader: File,
+    writer: File,
+    poller: Epoll,
+}
+
+impl FdTransport {
+    pub fn new(reader: File, writer: File) -> IOResult<Self> {
+        let poller = Epoll::new()?;
+        let reader = poller.add(reader, PollOpts::IN)?.into_file();
+        Ok(Self { reader, writer, poller })
+    }
+
+    /// Single descriptor used both ways, such as a tty, a pty or a socket
+    pub fn duplex(file: File) -> IOResult<Self> {
+        let writer = file.try_clone()?;
+        Self::new(file, writer)
+    }
+
+    pub fn reader(&self) -> &File { &self.reader }
+    pub fn writer(&self) -> &File { &self.writer }
+}
+
+impl Read for FdTransport {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> { self.reader.read(buf) }
+}
+
+impl Write for FdTransport {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> { self.writer.write(buf) }
+    fn flush(&mut self) -> IOResult<()> { self.writer.flush() }
+}
+
+impl Transport for FdTransport {
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> {
+        match timeout {
+            None => { self.poller.wait_one()?; Ok(true) }
+            Some(timeout) => Ok(self.poller.wait_one_timeout(timeout)?.is_some()),
+        }
+    }
+}
+
+/// The OC2 HLAPI serial console, put in raw mode until dropped
+pub struct HvcTransport {
+    terminal: Option<TermiosGuard>, // dropped first, while the descriptor is still open
+    transport: FdTransport,
+}
+
+impl HvcTransport {
+    pub fn open(path: impl AsRef<Path>) -> IOResult<Self> {
+        Self::open_with(path, &TtyConfig::default())
+    }
+
+    pub fn open_with(path: impl AsRef<Path>, config: &TtyConfig) -> IOResult<Self> {
+        Self::from_file_with(File::options().read(true).write(true).open(path)?, config)
+    }
+
+    /// Takes over an already opened descriptor, put in raw mode if it is a tty (an inherited pipe or socket is left as is)
+    pub fn from_file(file: File) -> IOResult<Self> {
+        Self::from_file_with(file, &TtyConfig::default())
+    }
+
+    pub fn from_file_with(file: File, config: &TtyConfig) -> IOResult<Self> {
+        let terminal = if file.is_terminal() { Some(TermiosGuard::apply(file.as_raw_fd(), config)?) } else { None };
+        Ok(Self { terminal, transport: FdTransport::duplex(file)? })
+    }
+
+    /// Settings the tty had before being opened, None if it isn't a tty
+    pub fn terminal(&self) -> Option<&TermiosGuard> { self.terminal.as_ref() }
+
+    /// # Safety
+    /// `descriptor` must be an open file descriptor owned by nobody else
+    pub unsafe fn from_raw_fd(descriptor: RawFd) -> IOResult<Self> { Self::from_file(File::from_raw_fd(descriptor)) }
+
+    pub fn main_bus() -> IOResult<Self> { Self::open(MAIN_BUS) }
+}
+
+impl Read for HvcTransport {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> { self.transport.read(buf) }
+}
+
+impl Write for HvcTransport {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> { self.transport.write(buf) }
+    fn flush(&mut self) -> IOResult<()> { self.transport.flush() }
+}
+
+impl Transport for HvcTransport {
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> { self.transport.wait_readable(timeout) }
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    bytes: VecDeque<u8>,
+    closed: bool, // the writing end got dropped
+}
+
+impl Queue {
+    /// Waits for bytes or for the writing end to go away
+    fn wait(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        let state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.ready.wait_while(state, |state| state.bytes.is_empty() && !state.closed)
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Same as `wait`, returns false if nothing came in time
+    fn wait_timeout(&self, timeout: Duration) -> bool {
+        let state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (_state, result) = self.ready.wait_timeout_while(state, timeout, |state| state.bytes.is_empty() && !state.closed)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        !result.timed_out()
+    }
+
+    fn push(&self, bytes: &[u8]) {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).bytes.extend(bytes);
+        self.ready.notify_all();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).closed = true;
+        self.ready.notify_all();
+    }
+}
+
+/// In-memory byte queues, whatever gets written in one end can be read from the other one
+/// Reading blocks until the other end writes something, or returns EOF once it has been dropped
+pub struct MemoryTransport {
+    incoming: Arc<Queue>,
+    outgoing: Arc<Queue>,
+}
+
+impl MemoryTransport {
+    pub fn pair() -> (Self, Self) {
+        let (left, right) = (Arc::new(Queue::default()), Arc::new(Queue::default()));
+        (Self { incoming: left.clone(), outgoing: right.clone() }, Self { incoming: right, outgoing: left })
+    }
+
+    /// Bytes waiting to be read from this end
+    pub fn pending(&self) -> usize {
+        self.incoming.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).bytes.len()
+    }
+}
+
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let mut state = self.incoming.wait();
+        let count = buf.len().min(state.bytes.len());
+        for (slot, byte) in buf.iter_mut().zip(state.bytes.drain(..count)) { *slot = byte; }
+        Ok(count) // 0 once closed
+    }
+}
+
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.outgoing.push(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IOResult<()> { Ok(()) }
+}
+
+impl Transport for MemoryTransport {
+    fn wait_readable(&mut self, timeout: Option<Duration>) -> IOResult<bool> {
+        match timeout {
+            None => { drop(self.incoming.wait()); Ok(true) }
+            Some(timeout) => Ok(self.incoming.wait_timeout(timeout)),
+        }
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) { self.outgoing.close(); }
+}
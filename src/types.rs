@@ -1,12 +1,56 @@
 use serde::{Serialize, Deserialize};
+use crate::signature::JavaType;
 
 pub type HLAPIDeviceHandle = uuid::Uuid;
 
-//pub type Empty = [(); 0]; // TODO: verify if this works
-#[derive(Serialize, Deserialize)] pub struct Empty { } // used as the empty parameters specifier
+#[derive(Clone, Copy, Debug, Default)] pub struct Empty { } // used as the empty parameters specifier, sent as `[]`
 pub const EMPTY: Empty = Empty { };
 pub const NOTHING: Void = None;
 
+impl Serialize for Empty {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::ser::SerializeSeq::end(serializer.serialize_seq(Some(0))?)
+    }
+}
+
+/// Anything empty: `[]`, `{}` (as `Empty` used to be sent) or `null`
+impl<'de> Deserialize<'de> for Empty {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EmptyVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EmptyVisitor {
+            type Value = Empty;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result { formatter.write_str("an empty array") }
+
+            fn visit_unit<E: serde::de::Error>(self) -> Result<Empty, E> { Ok(EMPTY) }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Empty, A::Error> {
+                match seq.next_element::<serde::de::IgnoredAny>()? {
+                    None => Ok(EMPTY),
+                    Some(_) => Err(serde::de::Error::invalid_length(1, &self)),
+                }
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Empty, A::Error> {
+                match map.next_key::<serde::de::IgnoredAny>()? {
+                    None => Ok(EMPTY),
+                    Some(_) => Err(serde::de::Error::invalid_length(1, &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(EmptyVisitor)
+    }
+}
+
+// Error messages sent back by the Java side
+pub const ERROR_MESSAGE_BUFFER_OVERFLOW: &str = "message too large";
+pub const ERROR_UNKNOWN_MESSAGE_TYPE: &str = "unknown message type";
+pub const ERROR_UNKNOWN_DEVICE: &str = "unknown device";
+pub const ERROR_UNKNOWN_METHOD: &str = "unknown method";
+pub const ERROR_INVALID_PARAMETER_SIGNATURE: &str = "invalid parameter signature";
+
 // TODO: Turn the tagged content enum into a generic struct, separating each entry, thus reducing enum size
 // Requires some research / code
 // generation, see https://canary.discord.com/channels/273534239310479360/274215136414400513/948701733612290131
@@ -43,9 +87,19 @@ pub enum HLAPIReceive<Tuple/*: DeserializeOwned */ = Void /*always None!*/> {
 
 impl<Tuple> HLAPIReceive<Tuple> {
     pub fn expect_result(self) -> Option<Tuple> { if let Self::Result(tuple) = self { Some(tuple) } else { None } }
+
+    /// Name of the variant, as sent in the `type` field
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::List(_) => "list",
+            Self::Methods(_) => "methods",
+            Self::Error(_) => "error",
+            Self::Result(_) => "result",
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HLAPIDeviceDescriptor {
@@ -74,5 +128,24 @@ pub struct HLAPIMethod {
 #[serde(rename_all = "camelCase")]
 pub struct HLAPIType {
     #[serde(rename = "type")]
-    data: String
-}
\ No newline at end of file
+    data: String,
+
+    // Only sent when the Java side knows them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>
+}
+
+impl HLAPIType {
+    pub fn new(type_name: impl Into<String>) -> Self { Self { data: type_name.into(), name: None, description: None } }
+
+    /// Java type name as sent, e.g. `"int"`, `"java.lang.String"` or `"[B"`
+    pub fn type_name(&self) -> &str { &self.data }
+    pub fn java_type(&self) -> JavaType { JavaType::parse(&self.data) }
+}
+
+impl HLAPIMethod {
+    pub fn return_java_type(&self) -> JavaType { JavaType::parse(&self.return_type) }
+    pub fn parameter_types(&self) -> impl Iterator<Item = JavaType> + '_ { self.parameters.iter().map(HLAPIType::java_type) }
+}
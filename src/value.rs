@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+/// Untyped HLAPI value, for calls whose types are only known at runtime (REPLs, scripting bridges...)
+#[derive(Clone, Debug, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HLAPIValue {
+    Null,
+    Bool(bool),
+    Integer(i64), // Java has nothing wider than a long
+    Float(f64),
+    String(String),
+    Array(Vec<HLAPIValue>),
+    Map(BTreeMap<String, HLAPIValue>),
+}
+
+impl HLAPIValue {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Bool(_) => "boolean",
+            Self::Integer(_) => "integer",
+            Self::Float(_) => "float",
+            Self::String(_) => "string",
+            Self::Array(_) => "array",
+            Self::Map(_) => "map",
+        }
+    }
+
+    pub fn is_null(&self) -> bool { matches!(self, Self::Null) }
+    pub fn as_bool(&self) -> Option<bool> { if let Self::Bool(value) = self { Some(*value) } else { None } }
+    pub fn as_i64(&self) -> Option<i64> { if let Self::Integer(value) = self { Some(*value) } else { None } }
+    /// Integers are floats too
+    pub fn as_f64(&self) -> Option<f64> {
+        match self { Self::Float(value) => Some(*value), Self::Integer(value) => Some(*value as f64), _ => None }
+    }
+    pub fn as_str(&self) -> Option<&str> { if let Self::String(value) = self { Some(value) } else { None } }
+    pub fn as_array(&self) -> Option<&[HLAPIValue]> { if let Self::Array(values) = self { Some(values) } else { None } }
+    pub fn as_map(&self) -> Option<&BTreeMap<String, HLAPIValue>> { if let Self::Map(map) = self { Some(map) } else { None } }
+
+    /// Array element or map entry
+    pub fn get(&self, index: impl ValueIndex) -> Option<&HLAPIValue> { index.index_into(self) }
+}
+
+/// Either a position in an array or a key in a map, see `HLAPIValue::get`
+pub trait ValueIndex { fn index_into(self, value: &HLAPIValue) -> Option<&HLAPIValue>; }
+
+impl ValueIndex for usize {
+    fn index_into(self, value: &HLAPIValue) -> Option<&HLAPIValue> { value.as_array()?.get(self) }
+}
+
+impl ValueIndex for &str {
+    fn index_into(self, value: &HLAPIValue) -> Option<&HLAPIValue> { value.as_map()?.get(self) }
+}
+
+impl Display for HLAPIValue {
+    /// As JSON
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Value::from(self.clone()).fmt(f)
+    }
+}
+
+impl From<Value> for HLAPIValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(value) => Self::Bool(value),
+            Value::Number(number) => match number.as_i64() {
+                Some(integer) => Self::Integer(integer),
+                None => Self::Float(number.as_f64().unwrap_or(f64::NAN)), // only past i64::MAX
+            },
+            Value::String(value) => Self::String(value),
+            Value::Array(values) => Self::Array(values.into_iter().map(Self::from).collect()),
+            Value::Object(map) => Self::Map(map.into_iter().map(|(key, value)| (key, Self::from(value))).collect()),
+        }
+    }
+}
+
+impl From<HLAPIValue> for Value {
+    /// NaN and infinite floats become null, as JSON can't hold them
+    fn from(value: HLAPIValue) -> Self {
+        match value {
+            HLAPIValue::Null => Value::Null,
+            HLAPIValue::Bool(value) => Value::Bool(value),
+            HLAPIValue::Integer(value) => Value::from(value),
+            HLAPIValue::Float(value) => Value::from(value),
+            HLAPIValue::String(value) => Value::String(value),
+            HLAPIValue::Array(values) => Value::Array(values.into_iter().map(Value::from).collect()),
+            HLAPIValue::Map(map) => Value::Object(map.into_iter().map(|(key, value)| (key, Value::from(value))).collect()),
+        }
+    }
+}
+
+macro_rules! integer_conversions {
+    ($($integer:ty),*) => {$(
+        impl From<$integer> for HLAPIValue {
+            fn from(value: $integer) -> Self { Self::Integer(value.into()) }
+        }
+
+        /// Fails when out of range, giving the value back
+        impl TryFrom<HLAPIValue> for $integer {
+            type Error = HLAPIValue;
+            fn try_from(value: HLAPIValue) -> Result<Self, Self::Error> {
+                match value {
+                    HLAPIValue::Integer(integer) => <$integer>::try_from(integer).map_err(|_| value),
+                    other => Err(other),
+                }
+            }
+        }
+    )*};
+}
+
+integer_conversions!(i8, i16, i32, i64, u8, u16, u32);
+
+impl From<bool> for HLAPIValue {
+    fn from(value: bool) -> Self { Self::Bool(value) }
+}
+
+impl From<f32> for HLAPIValue {
+    fn from(value: f32) -> Self { Self::Float(value.into()) }
+}
+
+impl From<f64> for HLAPIValue {
+    fn from(value: f64) -> Self { Self::Float(value) }
+}
+
+impl From<char> for HLAPIValue {
+    fn from(value: char) -> Self { Self::String(value.into()) }
+}
+
+impl From<String> for HLAPIValue {
+    fn from(value: String) -> Self { Self::String(value) }
+}
+
+impl From<&str> for HLAPIValue {
+    fn from(value: &str) -> Self { Self::String(value.to_owned()) }
+}
+
+impl<T: Into<HLAPIValue>> From<Vec<T>> for HLAPIValue {
+    fn from(values: Vec<T>) -> Self { Self::Array(values.into_iter().map(Into::into).collect()) }
+}
+
+impl<T: Into<HLAPIValue>> From<Option<T>> for HLAPIValue {
+    fn from(value: Option<T>) -> Self { value.map_or(Self::Null, Into::into) }
+}
+
+impl<T: Into<HLAPIValue>> From<BTreeMap<String, T>> for HLAPIValue {
+    fn from(map: BTreeMap<String, T>) -> Self { Self::Map(map.into_iter().map(|(key, value)| (key, value.into())).collect()) }
+}
+
+impl<T: Into<HLAPIValue>> FromIterator<T> for HLAPIValue {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self { Self::Array(values.into_iter().map(Into::into).collect()) }
+}
+
+/// Fails on any other kind, giving the value back
+impl TryFrom<HLAPIValue> for bool {
+    type Error = HLAPIValue;
+    fn try_from(value: HLAPIValue) -> Result<Self, Self::Error> { value.as_bool().ok_or(value) }
+}
+
+impl TryFrom<HLAPIValue> for f64 {
+    type Error = HLAPIValue;
+    fn try_from(value: HLAPIValue) -> Result<Self, Self::Error> { value.as_f64().ok_or(value) }
+}
+
+impl TryFrom<HLAPIValue> for String {
+    type Error = HLAPIValue;
+    fn try_from(value: HLAPIValue) -> Result<Self, Self::Error> {
+        if let HLAPIValue::String(value) = value { Ok(value) } else { Err(value) }
+    }
+}
+
+impl TryFrom<HLAPIValue> for Vec<HLAPIValue> {
+    type Error = HLAPIValue;
+    fn try_from(value: HLAPIValue) -> Result<Self, Self::Error> {
+        if let HLAPIValue::Array(values) = value { Ok(values) } else { Err(value) }
+    }
+}
@@ -0,0 +1,166 @@
+use std::ops::ControlFlow;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::types::*;
+use crate::error::*;
+use crate::transport::Transport;
+use crate::HLAPIBus;
+
+/// Default delay between two `list` requests
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    DeviceAdded(HLAPIDeviceDescriptor),
+    DeviceRemoved(HLAPIDeviceDescriptor),
+    /// Same device, other components, e.g. a card got swapped
+    ComponentsChanged { device_id: HLAPIDeviceHandle, before: Vec<String>, after: Vec<String> },
+}
+
+impl DeviceEvent {
+    pub fn device_id(&self) -> HLAPIDeviceHandle {
+        match self {
+            Self::DeviceAdded(descriptor) | Self::DeviceRemoved(descriptor) => descriptor.device_id,
+            Self::ComponentsChanged { device_id, .. } => *device_id,
+        }
+    }
+}
+
+/// Events turning the `before` device list into the `after` one, removals first
+pub fn diff(before: &[HLAPIDeviceDescriptor], after: &[HLAPIDeviceDescriptor]) -> Vec<DeviceEvent> {
+    fn find(list: &[HLAPIDeviceDescriptor], device_id: HLAPIDeviceHandle) -> Option<&HLAPIDeviceDescriptor> {
+        list.iter().find(|descriptor| descriptor.device_id == device_id)
+    }
+    let mut events = Vec::new();
+
+    for old in before {
+        match find(after, old.device_id) {
+            None => events.push(DeviceEvent::DeviceRemoved(old.clone())),
+            Some(new) if !same_components(&old.components, &new.components) => events.push(DeviceEvent::ComponentsChanged {
+                device_id: old.device_id, before: old.components.clone(), after: new.components.clone(),
+            }),
+            Some(_) => { }
+        }
+    }
+    for new in after {
+        if find(before, new.device_id).is_none() { events.push(DeviceEvent::DeviceAdded(new.clone())); }
+    }
+    events
+}
+
+/// Order doesn't matter
+fn same_components(left: &[String], right: &[String]) -> bool {
+    left.len() == right.len() && left.iter().all(|component| right.contains(component))
+}
+
+/// Periodically diffs the device list, see `poll`, `run`, `events` and `spawn`
+pub struct DeviceWatcher {
+    interval: Duration,
+    known: Option<Vec<HLAPIDeviceDescriptor>>, // None until the first poll
+    last_poll: Option<Instant>,
+}
+
+impl DeviceWatcher {
+    pub fn new(interval: Duration) -> Self { Self { interval, known: None, last_poll: None } }
+
+    pub fn interval(&self) -> Duration { self.interval }
+    pub fn set_interval(&mut self, interval: Duration) { self.interval = interval; }
+
+    /// Last known device list
+    pub fn devices(&self) -> &[HLAPIDeviceDescriptor] { self.known.as_deref().unwrap_or_default() }
+
+    /// Takes the current device list as the baseline without reporting anything, otherwise the first poll reports every device as added
+    pub fn prime<T: Transport>(&mut self, bus: &mut HLAPIBus<T>) -> HLAPIResult<()> {
+        self.known = Some(bus.refresh_list()?);
+        self.last_poll = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Lists the devices right away, and returns what changed since the last poll
+    pub fn poll<T: Transport>(&mut self, bus: &mut HLAPIBus<T>) -> HLAPIResult<Vec<DeviceEvent>> {
+        let devices = bus.refresh_list()?;
+        self.last_poll = Some(Instant::now());
+
+        let events = diff(self.devices(), &devices);
+        for event in &events { // their methods may have changed too
+            if !matches!(event, DeviceEvent::DeviceAdded(_)) { bus.invalidate_device(event.device_id()); }
+        }
+        self.known = Some(devices);
+        Ok(events)
+    }
+
+    /// Time left before the next poll is due, to be used as timeout by an external event loop
+    pub fn time_until_due(&self) -> Duration {
+        self.last_poll.map_or(Duration::ZERO, |last| self.interval.saturating_sub(last.elapsed()))
+    }
+
+    /// Same as `poll`, only once the interval elapsed
+    pub fn poll_due<T: Transport>(&mut self, bus: &mut HLAPIBus<T>) -> HLAPIResult<Vec<DeviceEvent>> {
+        if self.time_until_due().is_zero() { self.poll(bus) } else { Ok(Vec::new()) }
+    }
+
+    /// Blocks on the bus until the next poll is due, anything received in the meantime being stray answers and dropped
+    pub fn wait<T: Transport>(&self, bus: &mut HLAPIBus<T>) -> HLAPIResult<()> {
+        loop {
+            let remaining = self.time_until_due();
+            if remaining.is_zero() { return Ok(()); }
+            if bus.transport_mut().wait_readable(Some(remaining))? {
+                bus.drain(Duration::ZERO)?;
+            }
+        }
+    }
+
+    /// Polls forever, calling `callback` for every event until it breaks
+    pub fn run<T: Transport>(&mut self, bus: &mut HLAPIBus<T>, mut callback: impl FnMut(DeviceEvent) -> ControlFlow<()>) -> HLAPIResult<()> {
+        loop {
+            self.wait(bus)?;
+            for event in self.poll(bus)? {
+                if callback(event).is_break() { return Ok(()); }
+            }
+        }
+    }
+
+    /// Blocking iterator over the events
+    pub fn events<'w, T: Transport>(&'w mut self, bus: &'w mut HLAPIBus<T>) -> Events<'w, T> {
+        Events { watcher: self, bus, pending: Vec::new() }
+    }
+
+    /// Watches from another thread, until the receiver gets dropped, the thread then hands the bus back
+    pub fn spawn<T: Transport + Send + 'static>(mut self, mut bus: HLAPIBus<T>) -> (Receiver<HLAPIResult<DeviceEvent>>, JoinHandle<HLAPIBus<T>>) {
+        let (sender, receiver) = channel();
+        let thread = std::thread::spawn(move || {
+            for event in self.events(&mut bus) {
+                if sender.send(event).is_err() { break; }
+            }
+            bus
+        });
+        (receiver, thread)
+    }
+}
+
+impl Default for DeviceWatcher {
+    fn default() -> Self { Self::new(POLL_INTERVAL) }
+}
+
+/// See `DeviceWatcher::events`, never ends on its own
+pub struct Events<'w, T: Transport> {
+    watcher: &'w mut DeviceWatcher,
+    bus: &'w mut HLAPIBus<T>,
+    pending: Vec<DeviceEvent>, // reversed
+}
+
+impl<T: Transport> Iterator for Events<'_, T> {
+    type Item = HLAPIResult<DeviceEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let polled = self.watcher.wait(self.bus).and_then(|()| self.watcher.poll(self.bus));
+            match polled {
+                Ok(mut events) => { events.reverse(); self.pending = events; }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        self.pending.pop().map(Ok)
+    }
+}
@@ -0,0 +1,67 @@
+use serde_json::{json, Value};
+use oc2devices::{args, HLAPIArgs};
+use oc2devices::mock::{MockBus, MockDevice, MockTransport};
+use oc2devices::types::{HLAPIDeviceHandle, HLAPISend};
+
+const DEVICE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+#[derive(HLAPIArgs)]
+struct SetOutput { side: String, value: i32, pulse: Option<u32> }
+
+#[derive(HLAPIArgs)]
+struct Leading { first: Option<i32>, second: i32 }
+
+#[derive(HLAPIArgs)]
+struct Gaps { name: &'static str, count: i32, skip: Option<i32>, limit: Option<i32> }
+
+#[derive(HLAPIArgs)]
+struct Positional(&'static str, Option<bool>);
+
+#[derive(HLAPIArgs)]
+struct Nothing;
+
+#[derive(HLAPIArgs)]
+struct Generic<T> { value: T, extra: Option<T> }
+
+fn encoded(args: impl HLAPIArgs) -> Value { serde_json::to_value(args).unwrap() }
+
+#[test]
+fn fields_are_sent_in_order() {
+    assert_eq!(encoded(SetOutput { side: "up".into(), value: 15, pulse: Some(2) }), json!(["up", 15, 2]));
+    assert_eq!(encoded(Positional("a", Some(true))), json!(["a", true]));
+    assert_eq!(encoded(Generic { value: 1.5, extra: Some(2.5) }), json!([1.5, 2.5]));
+}
+
+#[test]
+fn trailing_nones_are_left_out() {
+    assert_eq!(encoded(SetOutput { side: "up".into(), value: 15, pulse: None }), json!(["up", 15]));
+    assert_eq!(encoded(Gaps { name: "a", count: 1, skip: None, limit: None }), json!(["a", 1]));
+    assert_eq!(encoded(Positional("a", None)), json!(["a"]));
+    assert_eq!(encoded(Generic::<i32> { value: 1, extra: None }), json!([1]));
+    assert_eq!(encoded(Nothing), json!([]));
+}
+
+#[test]
+fn inner_nones_are_sent_as_null() {
+    assert_eq!(encoded(Leading { first: None, second: 1 }), json!([null, 1]));
+    assert_eq!(encoded(Gaps { name: "a", count: 1, skip: None, limit: Some(3) }), json!(["a", 1, null, 3]));
+}
+
+#[test]
+fn args_macro_builds_tuples() {
+    assert_eq!(encoded(args!()), json!([]));
+    assert_eq!(encoded(args!("up")), json!(["up"]));
+    assert_eq!(encoded(args!("up", 15,)), json!(["up", 15]));
+}
+
+#[test]
+fn derived_args_pick_the_overload() {
+    let device = MockDevice::new(DEVICE, ["redstone"])
+        .method("setRedstoneOutput", &["java.lang.String", "int"], "int", |_| Ok(json!(2)))
+        .method("setRedstoneOutput", &["java.lang.String", "int", "int"], "int", |_| Ok(json!(3)));
+    let mut bus = MockBus::new(MockTransport::new().with_device(device));
+
+    assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "setRedstoneOutput", SetOutput { side: "up".into(), value: 15, pulse: None }).unwrap(), 2);
+    assert_eq!(bus.raw_call::<_, _, i32>(DEVICE, "setRedstoneOutput", SetOutput { side: "up".into(), value: 15, pulse: Some(4) }).unwrap(), 3);
+    assert!(matches!(&bus.transport().received()[1], HLAPISend::Invoke { parameters, .. } if *parameters == json!(["up", 15, 4])));
+}
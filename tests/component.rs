@@ -0,0 +1,65 @@
+use serde_json::json;
+use oc2devices::hlapi_component;
+use oc2devices::error::{HLAPIError, HLAPIResult};
+use oc2devices::mock::{MockBus, MockDevice, MockTransport};
+use oc2devices::types::{HLAPIDeviceHandle, HLAPISend};
+
+const REDSTONE: HLAPIDeviceHandle = HLAPIDeviceHandle::from_u128(1);
+
+#[hlapi_component("redstone")]
+trait Redstone {
+    fn get_redstone_input(&mut self, side: String) -> i32;
+    #[hlapi(name = "setRedstoneOutput")]
+    fn set_output(&mut self, side: &str, value: i32);
+    fn get_name(&mut self) -> HLAPIResult<String>;
+}
+
+fn device() -> MockDevice {
+    MockDevice::new(REDSTONE, ["redstone"])
+        .method("getRedstoneInput", &["java.lang.String"], "int", |args| Ok(json!(if args[0] == "up" { 15 } else { 0 })))
+        .method("setRedstoneOutput", &["java.lang.String", "int"], "boolean", |_| Ok(json!(true)))
+        .method("getName", &[], "java.lang.String", |_| Ok(json!("lever")))
+}
+
+#[test]
+fn declares_java_names_and_signatures() {
+    let methods: Vec<String> = RedstoneClient::<MockTransport>::METHODS.iter().map(|method| method.signature()).collect();
+    assert_eq!(methods, ["getRedstoneInput(String): i32", "setRedstoneOutput(& str, i32): ()", "getName(): String"]);
+    assert_eq!(RedstoneClient::<MockTransport>::COMPONENT, "redstone");
+}
+
+#[test]
+fn calls_the_device() {
+    let mut bus = MockBus::new(MockTransport::new().with_device(device()));
+    let mut redstone = RedstoneClient::find(&mut bus).unwrap();
+    assert_eq!(redstone.device(), REDSTONE);
+    assert_eq!(redstone.get_redstone_input("up".into()).unwrap(), 15);
+    redstone.set_output("down", 7).unwrap(); // whatever gets returned is dropped
+    assert_eq!(redstone.get_name().unwrap(), "lever");
+
+    assert!(matches!(&bus.transport().received()[2], HLAPISend::Invoke { method_name, parameters, .. }
+        if method_name == "setRedstoneOutput" && *parameters == json!(["down", 7])));
+}
+
+#[test]
+fn verifies_against_the_device() {
+    let mut bus = MockBus::new(MockTransport::new().with_device(device()));
+    assert!(RedstoneClient::find_verified(&mut bus).is_ok());
+
+    let mismatched = MockDevice::new(REDSTONE, ["redstone"])
+        .method("getRedstoneInput", &["int"], "int", |_| Ok(json!(0)))
+        .method("setRedstoneOutput", &["java.lang.String", "int"], "void", |_| Ok(json!(null)))
+        .method("getName", &[], "java.lang.String", |_| Ok(json!("lever")));
+    let mut bus = MockBus::new(MockTransport::new().with_device(mismatched));
+    match RedstoneClient::verified(&mut bus, REDSTONE) {
+        Err(HLAPIError::InvalidArguments(error)) => assert_eq!(error.method, "getRedstoneInput"),
+        other => panic!("{:?}", other.err()),
+    }
+
+    let missing = MockDevice::new(REDSTONE, ["redstone"]).method("getRedstoneInput", &["java.lang.String"], "int", |_| Ok(json!(0)));
+    let mut bus = MockBus::new(MockTransport::new().with_device(missing));
+    match RedstoneClient::verified(&mut bus, REDSTONE) {
+        Err(HLAPIError::InvalidArguments(error)) => assert!(error.method == "setRedstoneOutput" && error.signatures.is_empty()),
+        other => panic!("{:?}", other.err()),
+    }
+}